@@ -0,0 +1,102 @@
+#![no_main]
+
+use std::collections::{HashMap, HashSet};
+
+use libfuzzer_sys::fuzz_target;
+use mdbook_typstpdf::{prelude_functions, Converter, ResolvedImage};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use typst_syntax::{SyntaxKind, SyntaxNode};
+
+// the nodes only the markdown structure makes, never its text
+const STRUCTURE: [SyntaxKind; 8] = [
+    SyntaxKind::Heading,
+    SyntaxKind::ListItem,
+    SyntaxKind::EnumItem,
+    SyntaxKind::TermItem,
+    SyntaxKind::Ref,
+    SyntaxKind::LineComment,
+    SyntaxKind::BlockComment,
+    SyntaxKind::Equation,
+];
+
+// the markdown extensions the converter reads
+fn markdown_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    options
+}
+
+// a heading id or class as the converter writes it in a label
+fn typst_label(name: &str) -> String {
+    name.trim().chars().map(|c| if c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':') { c } else { '-' }).collect()
+}
+
+// how many of the STRUCTURE nodes the markdown should convert to, and the labels of its headings
+fn expected_structure(markdown: &str) -> (HashMap<SyntaxKind, usize>, HashSet<String>) {
+    let mut counts = HashMap::new();
+    let mut labels = HashSet::new();
+    let mut ordered = Vec::new();
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        match event {
+            Event::Start(Tag::Heading { id, classes, .. }) => {
+                // these are written with the heading function
+                if !classes.iter().any(|class| matches!(class.as_ref(), "unnumbered" | "unlisted")) {
+                    *counts.entry(SyntaxKind::Heading).or_default() += 1;
+                }
+                labels.extend(id.map(|id| format!("<{}>", typst_label(&id))));
+                labels.extend(classes.iter().map(|class| format!("<mdbook-class-{}>", typst_label(class))));
+            }
+            Event::Start(Tag::List(start)) => ordered.push(start.is_some()),
+            Event::End(TagEnd::List(_)) => {
+                ordered.pop();
+            }
+            Event::Start(Tag::Item) => {
+                let kind = if ordered.last() == Some(&true) { SyntaxKind::EnumItem } else { SyntaxKind::ListItem };
+                *counts.entry(kind).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+    (counts, labels)
+}
+
+// the STRUCTURE nodes and the labels in markup, the ones in arguments are link targets
+fn found_structure(node: &SyntaxNode, in_arguments: bool, counts: &mut HashMap<SyntaxKind, usize>, labels: &mut Vec<String>) {
+    match node.kind() {
+        SyntaxKind::Label if !in_arguments => labels.push(node.text().to_string()),
+        kind if STRUCTURE.contains(&kind) => *counts.entry(kind).or_default() += 1,
+        _ => {}
+    }
+    for child in node.children() {
+        found_structure(child, node.kind() == SyntaxKind::Args, counts, labels);
+    }
+}
+
+// any markdown converts to Typst that parses without errors, and its text stays text
+fuzz_target!(|markdown: &str| {
+    let converter = Converter::builder()
+        .element_functions(prelude_functions())
+        // no file system or network access, every image is local
+        .image_resolver(|url, _options| Ok(ResolvedImage { path: url.to_string(), width: None, asset: None }))
+        .build();
+    let typst = converter.convert(markdown).typst;
+    let root = typst_syntax::parse(&typst);
+    let errors = root.errors();
+    assert!(errors.is_empty(), "{:?}\n{}", errors, typst);
+
+    let (expected, heading_labels) = expected_structure(markdown);
+    let mut found = HashMap::new();
+    let mut labels = Vec::new();
+    found_structure(&root, false, &mut found, &mut labels);
+    for kind in STRUCTURE {
+        let count = |counts: &HashMap<SyntaxKind, usize>| counts.get(&kind).copied().unwrap_or_default();
+        assert_eq!(count(&found), count(&expected), "{:?} nodes in\n{}", kind, typst);
+    }
+    for label in labels {
+        assert!(heading_labels.contains(&label), "label {} in\n{}", label, typst);
+    }
+});
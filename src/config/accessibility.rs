@@ -0,0 +1,116 @@
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::{de, Deserialize, Deserializer};
+
+use super::{converter::RE_HTML_IMG_ALT, front_matter::FrontMatter, Config};
+
+/// Images of a chapter without alt text, by their path or URL
+pub fn images_without_alt(content: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut image: Option<(String, String)> = None;
+    for event in Parser::new(FrontMatter::strip(content)) {
+        match event {
+            Event::Start(Tag::Image { dest_url, .. }) => image = Some((dest_url.to_string(), String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, alt)) = &mut image {
+                    alt.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Image) => {
+                if let Some((url, alt)) = image.take() {
+                    if alt.trim().is_empty() {
+                        result.push(url);
+                    }
+                }
+            }
+            Event::Html(html) | Event::InlineHtml(html) if html.contains("<img") => {
+                let has_alt = RE_HTML_IMG_ALT.captures(&html).is_some_and(|cap| !cap[1].trim().is_empty());
+                if !has_alt {
+                    result.push(html.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Check `pdf_standard` is a comma separated list of values.
+/// Which standards are supported depends on the installed typst, it reports the ones it doesn't know.
+pub fn validate_pdf_standard(standard: &str) -> Result<(), String> {
+    for value in standard.split(',') {
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            return Err(format!("pdf_standard {:?}: expected comma separated values without spaces, like \"a-2b\" or \"1.7,a-3b\"", standard));
+        }
+    }
+    Ok(())
+}
+
+/// `deserialize_with` for `pdf_standard`, a malformed list fails when the config is loaded
+pub fn deserialize_pdf_standard<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let standard = Option::<String>::deserialize(deserializer)?;
+    if let Some(standard) = &standard {
+        validate_pdf_standard(standard).map_err(de::Error::custom)?;
+    }
+    Ok(standard)
+}
+
+impl Config {
+    /// Check what the PDF standards in `pdf_standard` need from the book before compiling:
+    /// a title, a language and alt text on every image.
+    /// Every violation is reported, with its chapter, and fails the build.
+    pub fn check_pdf_standard(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+        let Some(standard) = &self.pdf_standard else {
+            return Ok(());
+        };
+        let mut violations = 0;
+        let metadata = self.get_document_metadata(ctx);
+        if metadata.title.is_none() {
+            log::error!("pdf_standard {}: the book has no title, set title in [book]", standard);
+            violations += 1;
+        }
+        if metadata.language.is_none() {
+            log::error!("pdf_standard {}: the book has no language, set language in [book]", standard);
+            violations += 1;
+        }
+        for item in ctx.book.iter() {
+            if let mdbook::book::BookItem::Chapter(chapter) = item {
+                let front_matter = FrontMatter::extract(&chapter.content).0.unwrap_or_default();
+                if chapter.path.is_none() || front_matter.pdf.skip {
+                    continue;
+                }
+                for image in images_without_alt(&chapter.content) {
+                    log::error!("pdf_standard {}: chapter {}: image {} has no alt text", standard, chapter.name, image);
+                    violations += 1;
+                }
+            }
+        }
+        if violations > 0 {
+            return Err(anyhow::anyhow!("{} problems prevent a {} pdf, see the errors above", violations, standard));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_images_without_alt() {
+        let content = "![Logo](logo.png) ![](empty.png) ![ ](blank.png)\n\n<img src=\"a.png\" alt=\"A\">\n<img src=\"b.png\">\n";
+        assert_eq!(images_without_alt(content), vec!["empty.png", "blank.png", "<img src=\"b.png\">"]);
+    }
+
+    #[test]
+    fn test_pdf_standard() {
+        let config: Config = toml::from_str("pdf_standard = \"a-2b,1.7\"").unwrap();
+        assert_eq!(config.pdf_standard.as_deref(), Some("a-2b,1.7"));
+
+        // left to the installed typst, newer versions support PDF/UA
+        let config: Config = toml::from_str("pdf_standard = \"ua-1\"").unwrap();
+        assert_eq!(config.pdf_standard.as_deref(), Some("ua-1"));
+        let error = toml::from_str::<Config>("pdf_standard = \"a-2b, 1.7\"").unwrap_err().to_string();
+        assert!(error.contains("without spaces"), "{}", error);
+        assert!(toml::from_str::<Config>("pdf_standard = \"a-2b,\"").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fs, io,
+    path::Path,
+};
+
+use globset::GlobSet;
+use walkdir::{DirEntry, WalkDir};
+
+use super::{filter::build_glob_set, Config};
+
+// version control folders and files that never belong to the output
+const VCS_NAMES: &[&str] = &["CVS", "_darcs"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CopyStats {
+    pub copied: usize,
+    pub unchanged: usize,
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    // the root is given explicitly, only its content is filtered
+    if entry.depth() == 0 {
+        return false;
+    }
+    let name = entry.file_name().to_string_lossy();
+    name.starts_with('.') || VCS_NAMES.contains(&name.as_ref())
+}
+
+// same size and modification time as the previous copy
+fn is_unchanged(source: &fs::Metadata, target: &Path) -> bool {
+    match fs::metadata(target) {
+        Ok(target) => target.len() == source.len() && target.modified().ok() == source.modified().ok(),
+        Err(_) => false,
+    }
+}
+
+/// Copy the files under `source` to `target`, keeping the folder structure.
+///
+/// Hidden and version control files are skipped, symbolic links are followed and the files they point to copied.
+/// With `patterns`, only files whose path relative to `source` matches are copied.
+/// Files with the same size and modification time as their copy are left alone.
+pub fn copy_tree(source: &Path, target: &Path, patterns: Option<&GlobSet>) -> anyhow::Result<CopyStats> {
+    let mut stats = CopyStats::default();
+    for entry in WalkDir::new(source).follow_links(true).into_iter().filter_entry(|entry| !is_hidden(entry)) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                // a broken link or a link loop only loses that entry
+                log::warn!("skipping template asset: {}", e);
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(source)?;
+        if let Some(patterns) = patterns {
+            if !patterns.is_match(relative.to_string_lossy().replace('\\', "/")) {
+                continue;
+            }
+        }
+
+        if copy_file(entry.path(), &target.join(relative))? {
+            stats.copied += 1;
+        } else {
+            stats.unchanged += 1;
+        }
+    }
+    Ok(stats)
+}
+
+/// Copy a file unless `dest` has the same size and modification time already, returns whether it was copied
+pub fn copy_file(source: &Path, dest: &Path) -> anyhow::Result<bool> {
+    let metadata = fs::metadata(source)?;
+    if is_unchanged(&metadata, dest) {
+        return Ok(false);
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // a previous copy of a read-only source is read-only too, it's replaced rather than written to
+    match fs::remove_file(dest) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut file = fs::File::create(dest)?;
+    io::copy(&mut fs::File::open(source)?, &mut file)?;
+    // the modification time is what tells an unchanged copy next time,
+    // it's set while the file is still writable and the permissions of the source come last
+    if let Ok(modified) = metadata.modified() {
+        file.set_modified(modified)?;
+    }
+    drop(file);
+    fs::set_permissions(dest, metadata.permissions())?;
+    Ok(true)
+}
+
+impl Config {
+    /// Copy the template directory tree, or only the files matching `assets`, to `typst/templates/`
+    pub fn prepare_template_assets(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+        let source_template_dir = self.get_template_dir(ctx);
+        if !source_template_dir.is_dir() {
+            log::debug!("template dir {} doesn't exist, no assets to copy", source_template_dir.display());
+            return Ok(());
+        }
+        let patterns = if self.assets.is_empty() { None } else { Some(build_glob_set(&self.assets)?) };
+        let target_template_dir = self.get_typst_templates_dir(ctx);
+        let stats = copy_tree(&source_template_dir, &target_template_dir, patterns.as_ref())?;
+        log::debug!(
+            "copied {} template assets from {} to {}, {} unchanged",
+            stats.copied, source_template_dir.display(), target_template_dir.display(), stats.unchanged
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_tree() {
+        let source = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let deep = source.path().join("images/logos/dark");
+        fs::create_dir_all(&deep).unwrap();
+        fs::create_dir_all(source.path().join(".git")).unwrap();
+        fs::write(deep.join("logo.svg"), "<svg/>").unwrap();
+        fs::write(source.path().join("template.typ"), "#let t(body) = body").unwrap();
+        fs::write(source.path().join(".git/HEAD"), "ref").unwrap();
+        fs::write(source.path().join("images/.DS_Store"), "").unwrap();
+
+        let stats = copy_tree(source.path(), target.path(), None).unwrap();
+        assert_eq!(stats, CopyStats { copied: 2, unchanged: 0 });
+        assert!(target.path().join("images/logos/dark/logo.svg").is_file());
+        assert!(!target.path().join(".git").exists());
+        assert!(!target.path().join("images/.DS_Store").exists());
+
+        let stats = copy_tree(source.path(), target.path(), None).unwrap();
+        assert_eq!(stats, CopyStats { copied: 0, unchanged: 2 });
+
+        let patterns = build_glob_set(&["images/**".to_string()]).unwrap();
+        let target = tempfile::tempdir().unwrap();
+        copy_tree(source.path(), target.path(), Some(&patterns)).unwrap();
+        assert!(target.path().join("images/logos/dark/logo.svg").is_file());
+        assert!(!target.path().join("template.typ").exists());
+    }
+
+    #[test]
+    fn test_copy_read_only_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("logo.svg");
+        let dest = dir.path().join("copy/logo.svg");
+        fs::write(&source, "<svg/>").unwrap();
+        let mut permissions = fs::metadata(&source).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&source, permissions).unwrap();
+
+        assert!(copy_file(&source, &dest).unwrap());
+        assert!(fs::metadata(&dest).unwrap().permissions().readonly());
+        assert!(!copy_file(&source, &dest).unwrap());
+
+        // a changed source replaces the read-only copy
+        let mut permissions = fs::metadata(&source).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&source, permissions.clone()).unwrap();
+        fs::write(&source, "<svg></svg>").unwrap();
+        permissions.set_readonly(true);
+        fs::set_permissions(&source, permissions).unwrap();
+        assert!(copy_file(&source, &dest).unwrap());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "<svg></svg>");
+    }
+}
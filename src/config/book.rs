@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-use super::{Config, BEST_PRACTICE_TEMPLATE,  TARGET_CHAPTERS_DIR, TARGET_TEMPLATE_DIR};
+use super::{filter::{summary_tags, CompiledChapterFilter}, front_matter::FrontMatter, output::{split_document_name, SplitMode}, prelude::{get_shadowed_functions, prelude_import}, template::{TemplateEntry, TemplateSignature}, Config};
 
 impl Config {
     pub fn get_chapter_full_file_name(&self, chapter:&mdbook::book::Chapter, ctx: &mdbook::renderer::RenderContext) -> Option<PathBuf> {
@@ -16,7 +16,7 @@ impl Config {
     }
 
     pub fn get_chapter_relative_chapter_file_name(&self, chapter:&mdbook::book::Chapter, _ctx: &mdbook::renderer::RenderContext) -> Option<PathBuf> {
-        let chapter_dir = PathBuf::from(TARGET_CHAPTERS_DIR);
+        let chapter_dir = PathBuf::from(self.get_chapters_dir_name());
         match &chapter.source_path {
             None => None,
             Some(source_path) => {
@@ -26,37 +26,84 @@ impl Config {
             }
         }
     }
-    pub fn append_chapter_to_typst_output(&self, ctx: &mdbook::renderer::RenderContext, typst_output: &mut String) -> anyhow::Result<()> {
-        let book = &ctx.book;
+    pub fn append_chapter_to_typst_output(&self, ctx: &mdbook::renderer::RenderContext, typst_output: &mut String, template_name: Option<&str>) -> anyhow::Result<()> {
+        self.append_items_to_typst_output(ctx, &ctx.book.sections, typst_output, template_name)?;
+        Ok(())
+    }
+
+    // Append the include directives of `items` and their sub-chapters, returns how many lines were added
+    fn append_items_to_typst_output(&self, ctx: &mdbook::renderer::RenderContext, items: &[mdbook::book::BookItem], typst_output: &mut String, template_name: Option<&str>) -> anyhow::Result<usize> {
         let mut typ_content = Vec::new();
+        let filter = self.chapter_filter.compile()?;
         
-        for item in book.sections.iter() {
-            self.process_book_item(item, &mut typ_content, ctx)?;
+        for item in items {
+            self.process_book_item(item, &mut typ_content, template_name, &filter, &[], ctx)?;
         }
         
         // Add all include directives to the typst output
-        for line in typ_content {
+        for line in &typ_content {
             typst_output.push_str(&format!("{}\n", line));
         }
         
-        Ok(())
+        Ok(typ_content.len())
     }
     
     // Helper function to recursively process book items (chapters, sections)
-    fn process_book_item(&self, item: &mdbook::book::BookItem, typ_content: &mut Vec<String>, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+    #[allow(clippy::too_many_arguments)]
+    fn process_book_item(&self,
+        item: &mdbook::book::BookItem,
+        typ_content: &mut Vec<String>,
+        template_name: Option<&str>,
+        filter: &CompiledChapterFilter,
+        parent_tags: &[String], // sub chapters inherit the audience tags of their parents
+        ctx: &mdbook::renderer::RenderContext,
+    ) -> anyhow::Result<()> {
         match item {
             mdbook::book::BookItem::Chapter(chapter) => {
-                if let Some(chapter_path) = self.get_chapter_relative_chapter_file_name(chapter, ctx) {
-                    log::debug!("Including chapter: {} with path: {}", chapter.name, chapter_path.display());
-                
-                    // Add include directive for the chapter with its original path under "chapter"
-                    typ_content.push(format!("#include \"{}\"", chapter_path.to_string_lossy().replace('\\', "/")));
+                let front_matter = FrontMatter::extract(&chapter.content).0.unwrap_or_default();
+                let mut tags = parent_tags.to_vec();
+                tags.extend(front_matter.tags.iter().cloned());
+                tags.extend(summary_tags(&chapter.name));
+                match self.get_chapter_relative_chapter_file_name(chapter, ctx) {
+                    Some(_) if front_matter.pdf.skip => {
+                        log::debug!("Chapter {} is skipped by its front matter", chapter.name);
+                    }
+                    Some(_) if !filter.matches(chapter.source_path.as_deref().unwrap_or(Path::new("")), &tags) => {
+                        log::info!("Chapter {} is filtered out for template {:?}", chapter.name, template_name);
+                    }
+                    Some(chapter_path) => {
+                        log::debug!("Including chapter: {} with path: {}", chapter.name, chapter_path.display());
+                    
+                        // Add include directive for the chapter with its original path under "chapter"
+                        let chapter_path = chapter_path.to_string_lossy().replace('\\', "/");
+                        // nested chapters have their headings moved down a level per SUMMARY.md level,
+                        // so the outline and pdf bookmarks follow the book structure
+                        let mut include = match chapter.parent_names.len() {
+                            0 => format!("#include \"{}\"", chapter_path),
+                            depth => format!("#{{ set heading(offset: {}); include \"{}\" }}", depth, chapter_path),
+                        };
+                        if front_matter.pdf.landscape {
+                            include = format!("#page(flipped: true)[{}]", include);
+                        }
+                        if let Some(section) = &front_matter.pdf.template_section {
+                            if template_name.is_some() {
+                                include = format!("#{}[{}]", section, include);
+                            } else {
+                                log::warn!("Chapter {} asks for template section {}, but the template doesn't provide sections", chapter.name, section);
+                            }
+                        }
+                        if front_matter.pdf.page_break {
+                            typ_content.push("#pagebreak(weak: true)".to_string());
+                        }
+                        typ_content.push(include);
+                    }
+                    None => {}
                 }
                 
                 
                 // Process sub-items recursively
                 for sub_item in &chapter.sub_items {
-                    self.process_book_item(sub_item, typ_content, ctx)?;
+                    self.process_book_item(sub_item, typ_content, template_name, filter, &tags, ctx)?;
                 }
             },
             mdbook::book::BookItem::Separator => {
@@ -73,7 +120,9 @@ impl Config {
     }
 
     
-    pub fn convert_book(&self, _chapter_file_list: &mut [PathBuf], ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+    /// Write the book level typst files, one per template and, with `split`, one per top level chapter.
+    /// Returns the names of the written documents.
+    pub fn convert_book(&self, _chapter_file_list: &mut [PathBuf], ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<Vec<String>> {
         let target_template_dir = self.get_typst_templates_dir(ctx);
         if !target_template_dir.exists() {
             return Err(anyhow::anyhow!("template directory {} not found", target_template_dir.display()));
@@ -82,101 +131,138 @@ impl Config {
         // // Convert all markdown files preserving their original paths
         // self.convert_all_chapters(ctx)?;
         
+        let mut documents = Vec::new();
         // check the templates hashmap
         if self.templates.is_empty() {
-            let mut typst_output = String::new();
-            
-            // Don't add package imports here as they're now in each chapter file
-            // Just include the chapters
-            self.append_chapter_to_typst_output(ctx, &mut typst_output)?;
-
-            // write the typst_output to the file
-            self.write_typst_file(ctx, &typst_output, None)?;
-        } else {
-            for (name, file_name) in &self.templates {
-                let mut typst_output = String::new();
-                // get the file name from the template_file 
-                let file_name = Path::new(file_name).file_name().unwrap().to_str().unwrap();
-                let dst = target_template_dir.join(file_name);
-                // if the dst file doesn't exist or is not a file, error out
-                if !dst.exists() || !dst.is_file() {
-                    return Err(anyhow::anyhow!("template file {} not found", dst.display()));
+            match self.get_builtin_default_template()? {
+                Some(template) => documents.extend(self.convert_book_with_template(None, &template, ctx)?),
+                None => {
+                    // Don't add package imports here as they're now in each chapter file
+                    // Just include the chapters
+                    let typst_header = format!("{}{}", prelude_import(), self.get_document_metadata(ctx).to_typst());
+                    documents.extend(self.write_book_documents(&typst_header, None, ctx)?);
                 }
-
-                typst_output.push_str(&format!("#import \"{}/{}\": {}\n",TARGET_TEMPLATE_DIR,file_name,BEST_PRACTICE_TEMPLATE));
-                
-                // Templates still need their metadata and setup
-                typst_output.push_str("\n\n//Document Metadata\n");
-
-                typst_output.push_str("#let metadata = (\n");
-                for (key, value) in &self.template_parameters {
-                    // please escape the value if it contains double quotes
-                    let value = value.replace("\"", "\\\"");
-                    // please escape the \n in the value with \\n
-                    let value = value.replace("\n", "\\n");
-                    typst_output.push_str(&format!("    {}: \"{}\",\n", key, value));
-                }
-                typst_output.push_str(")\n");
-
-                typst_output.push_str(&format!("#show: doc => {}(\n",BEST_PRACTICE_TEMPLATE));
-                for key in self.template_parameters.keys() {
-                    typst_output.push_str(&format!("    {}: metadata.{},\n",key,key));
-                }
-                typst_output.push_str("  doc\n");
-                typst_output.push_str(")\n\n");
-
-                // append all chapter files to the typst_output
-                self.append_chapter_to_typst_output(ctx, &mut typst_output)?;
-
-                // write the typst_output to the file
-                self.write_typst_file(ctx, &typst_output, Some(name))?;
+            }
+        } else {
+            for (name, template) in &self.templates {
+                documents.extend(self.convert_book_with_template(Some(name), template, ctx)?);
             }
         }
 
-        Ok(())
+        Ok(documents)
     }
 
-    pub fn write_typst_file(&self, ctx: &mdbook::renderer::RenderContext, typst_output: &str, template_name:Option<&str>) -> anyhow::Result<()> {
+    // Write the whole book and, with `split`, every top level chapter, each starting with `typst_header`
+    fn write_book_documents(&self, typst_header: &str, template_name: Option<&str>, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<Vec<String>> {
         let book_name = self.get_book_name(template_name, ctx);
-        let typst_dir = self.get_typst_dir(ctx);
-        let output_file = typst_dir.join(format!("{}.typ", book_name));
-        std::fs::write(output_file, typst_output).map_err(|e| anyhow::anyhow!("failed to write typst file:{}", e))
-        // Ok(())
-    }
+        let mut typst_output = typst_header.to_string();
+        // append all chapter files to the typst_output
+        self.append_chapter_to_typst_output(ctx, &mut typst_output, template_name)?;
+        // write the typst_output to the file
+        self.write_typst_file(ctx, &typst_output, &book_name)?;
+        let mut documents = vec![book_name.clone()];
 
-    pub fn convert_book_to_pdf(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
-        if self.templates.is_empty() {
-            self.invoke_typst_command(ctx,  None)?;
-        }else {
-            for name in self.templates.keys() {
-                self.invoke_typst_command(ctx,  Some(name))?;
+        if self.split == Some(SplitMode::Chapter) {
+            let chapters = ctx.book.sections.iter().filter_map(|item| match item {
+                mdbook::book::BookItem::Chapter(chapter) => Some((item, chapter)),
+                _ => None,
+            });
+            for (index, (item, chapter)) in chapters.enumerate() {
+                let mut typst_output = typst_header.to_string();
+                // skipped and filtered out chapters don't get a document
+                if self.append_items_to_typst_output(ctx, std::slice::from_ref(item), &mut typst_output, template_name)? == 0 {
+                    continue;
+                }
+                let document_name = split_document_name(&book_name, index + 1, chapter);
+                self.write_typst_file(ctx, &typst_output, &document_name)?;
+                documents.push(document_name);
             }
         }
-        // log::info!("destination:{}", ctx.destination.display());
-        Ok(())
+        Ok(documents)
     }
 
-    pub fn invoke_typst_command(&self, ctx: &mdbook::renderer::RenderContext,  template_name:Option<&str>) -> anyhow::Result<()> {
-        let book_name = self.get_book_name(template_name, ctx);
-        let typst_dir = self.get_typst_dir(ctx);
-        let typst_file = typst_dir.join(format!("{}.typ", book_name));
-        if !typst_file.exists() || !typst_file.is_file() {
-            return Err(anyhow::anyhow!("typst file {} not found", typst_file.display()));
+    // Write the book level typst file for one template, `name` is None for the built-in default template
+    fn convert_book_with_template(&self, name: Option<&str>, template: &TemplateEntry, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<Vec<String>> {
+        // the template table values applied over the global ones
+        let config = match name {
+            Some(name) => self.for_template(name),
+            None => self.clone(),
+        };
+        let display_name = name.unwrap_or(template.file());
+        // the prelude first, the template's own functions shadow it
+        let mut typst_output = prelude_import();
+        let resolved = self.resolve_template(template, ctx)?;
+
+        // the entry function and its parameters are read from the template source
+        let signature = TemplateSignature::from_file(&resolved.source, template.entry())?;
+        let arguments = config.get_template_arguments(display_name, &signature, ctx)?;
+
+        // the template sections requested by chapter front matter are imported along with the template function,
+        // the built-in templates don't provide any
+        let mut imports = vec![signature.function.clone()];
+        if template.builtin().is_none() {
+            imports.extend(self.get_template_sections(ctx));
+        }
+        let shadowed = get_shadowed_functions(&resolved.source)?;
+        imports.extend(shadowed.iter().cloned());
+        typst_output.push_str(&format!("#import \"{}\": {}\n",resolved.import,imports.join(", ")));
+        
+        // pdf properties, a template setting them itself wins
+        typst_output.push_str(&config.get_document_metadata(ctx).to_typst());
+
+        // Templates still need their metadata and setup,
+        // the binding is namespaced so it doesn't shadow typst's own `metadata` element
+        typst_output.push_str("\n\n//Document Metadata\n");
+
+        typst_output.push_str("#let mdbook-metadata = (\n");
+        for (key, value) in arguments.positional.iter().chain(&arguments.named) {
+            let value = config.template_parameter_to_typst(value, ctx)?;
+            typst_output.push_str(&format!("    {}: {},\n", key, value));
         }
-        let pdf_dir = self.get_pdf_dir(ctx);
-        // create the pdf_dir if it doesn't exist
-        if !pdf_dir.exists() {
-            std::fs::create_dir_all(&pdf_dir)?;
+        typst_output.push_str(")\n");
+
+        typst_output.push_str(&format!("#show: doc => {}(\n",signature.function));
+        for (key, _) in &arguments.positional {
+            typst_output.push_str(&format!("    mdbook-metadata.{},\n",key));
         }
-        let output_file = pdf_dir.join(format!("{}.pdf", book_name));
-        // run the typst command to convert the typst file to pdf
-        let status = std::process::Command::new("typst").arg("compile").arg(&typst_file).arg(&output_file).status()?;
-        if !status.success() {
-            return Err(anyhow::anyhow!("failed to convert typst file:{} to pdf", typst_file.display()));
+        for (key, _) in &arguments.named {
+            typst_output.push_str(&format!("    {}: mdbook-metadata.{},\n",key,key));
         }
-        log::info!("converted typst file:{} to pdf:{}", typst_file.display(), output_file.display());
-        Ok(())
+        typst_output.push_str("  doc\n");
+        typst_output.push_str(")\n\n");
+
+        // the chapters call the prelude, it hands over to the template's functions
+        if !shadowed.is_empty() {
+            let functions: Vec<String> = shadowed.iter().map(|function| format!("{}: {}", function, function)).collect();
+            typst_output.push_str(&format!("#mdbook-shadow({})\n\n", functions.join(", ")));
+        }
+
+        config.write_book_documents(&typst_output, name, ctx)
     }
 
-    
-}
\ No newline at end of file
+    // Collect the distinct `template_section` values used by the chapters' front matter
+    fn get_template_sections(&self, ctx: &mdbook::renderer::RenderContext) -> Vec<String> {
+        let mut sections: Vec<String> = Vec::new();
+        for item in ctx.book.iter() {
+            if let mdbook::book::BookItem::Chapter(chapter) = item {
+                let front_matter = FrontMatter::extract(&chapter.content).0.unwrap_or_default();
+                if front_matter.pdf.skip {
+                    continue;
+                }
+                if let Some(section) = front_matter.pdf.template_section {
+                    if !sections.contains(&section) {
+                        sections.push(section);
+                    }
+                }
+            }
+        }
+        sections
+    }
+
+    pub fn write_typst_file(&self, ctx: &mdbook::renderer::RenderContext, typst_output: &str, document_name: &str) -> anyhow::Result<()> {
+        let typst_dir = self.get_typst_dir(ctx);
+        let output_file = typst_dir.join(format!("{}.typ", document_name));
+        std::fs::write(output_file, typst_output).map_err(|e| anyhow::anyhow!("failed to write typst file:{}", e))
+        // Ok(())
+    }
+}
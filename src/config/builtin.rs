@@ -0,0 +1,66 @@
+use super::{template::TemplateEntry, Config};
+
+/// a template file starting with this prefix refers to a template embedded in the binary,
+/// e.g. `screen = "builtin:report"`
+pub const BUILTIN_TEMPLATE_PREFIX: &str = "builtin:";
+
+/// `default_template` value that turns the built-in default template off
+pub const NO_DEFAULT_TEMPLATE: &str = "none";
+
+/// A template shipped inside the binary, written to `typst/templates/` when used
+pub struct BuiltinTemplate {
+    pub name: &'static str,
+    pub source: &'static str,
+}
+
+pub const BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    BuiltinTemplate { name: "book", source: include_str!("../templates/book.typ") },
+    BuiltinTemplate { name: "report", source: include_str!("../templates/report.typ") },
+    BuiltinTemplate { name: "article", source: include_str!("../templates/article.typ") },
+];
+
+impl BuiltinTemplate {
+    pub fn find(name: &str) -> anyhow::Result<&'static BuiltinTemplate> {
+        BUILTIN_TEMPLATES.iter().find(|template| template.name == name).ok_or_else(|| {
+            let names: Vec<&str> = BUILTIN_TEMPLATES.iter().map(|template| template.name).collect();
+            anyhow::anyhow!("unknown built-in template {}, available: {}", name, names.join(", "))
+        })
+    }
+
+    /// the file name in `typst/templates/`, prefixed so it can't clash with the user's templates
+    pub fn file_name(&self) -> String {
+        format!("mdbook-{}.typ", self.name)
+    }
+
+    pub fn entry(&self) -> TemplateEntry {
+        TemplateEntry::File(format!("{}{}", BUILTIN_TEMPLATE_PREFIX, self.name))
+    }
+}
+
+impl Config {
+    /// The built-in template used when no `templates` are configured, `None` when `default_template` is `none`
+    pub fn get_builtin_default_template(&self) -> anyhow::Result<Option<TemplateEntry>> {
+        if !self.templates.is_empty() || self.default_template == NO_DEFAULT_TEMPLATE {
+            return Ok(None);
+        }
+        Ok(Some(BuiltinTemplate::find(&self.default_template)?.entry()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::template::TemplateSignature;
+
+    #[test]
+    fn test_builtin_templates() {
+        for template in BUILTIN_TEMPLATES {
+            let signature = TemplateSignature::parse(template.source, None).unwrap();
+            assert_eq!(signature.function, format!("mdbook-{}", template.name));
+            assert!(signature.required().is_empty());
+            assert!(signature.named.contains(&"book_title".to_string()));
+            assert_eq!(template.entry().builtin(), Some(template.name));
+        }
+        assert!(BuiltinTemplate::find("poster").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use super::{chapter::local_image_paths, converter::CONVERTER_OUTPUT_VERSION, Config, TARGET_CACHE_DIR};
+
+/// The content hash of every converted chapter, by chapter file relative to the chapters folder.
+///
+/// Saved next to the cached chapters folder, a chapter whose hash didn't change is not converted again.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChapterCache {
+    pub chapters: BTreeMap<String, String>,
+}
+
+impl ChapterCache {
+    /// an unreadable or outdated manifest is an empty cache
+    pub fn load(path: &Path) -> ChapterCache {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|manifest| serde_json::from_str(&manifest).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_fresh(&self, key: &str, hash: &str) -> bool {
+        self.chapters.get(key).is_some_and(|cached| cached == hash)
+    }
+
+    /// Remove the files under `dir` that are neither a chapter of the cache nor referred to by one as `/<chapters_dir_name>/<path>`,
+    /// like the file of a removed chapter or an image only it showed, and the folders left empty. Returns how many files were removed.
+    pub fn prune(&self, dir: &Path, chapters_dir_name: &str) -> anyhow::Result<usize> {
+        let mut chapters = String::new();
+        for key in self.chapters.keys() {
+            if let Ok(content) = std::fs::read_to_string(dir.join(key)) {
+                chapters.push_str(&content);
+            }
+        }
+        let mut removed = 0;
+        for entry in WalkDir::new(dir).contents_first(true).into_iter().filter_map(Result::ok) {
+            let relative = entry.path().strip_prefix(dir)?.to_string_lossy().replace('\\', "/");
+            if entry.file_type().is_dir() {
+                // fails for the folders still in use
+                if entry.depth() > 0 {
+                    let _ = std::fs::remove_dir(entry.path());
+                }
+                continue;
+            }
+            if self.chapters.contains_key(&relative) || chapters.contains(&format!("/{}/{}", chapters_dir_name, relative)) {
+                continue;
+            }
+            std::fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+impl Config {
+    /// `cache_dir` relative to the book root, `<destination>/cache` by default
+    pub fn get_cache_dir(&self, ctx: &mdbook::renderer::RenderContext) -> PathBuf {
+        match &self.cache_dir {
+            Some(dir) => ctx.root.join(dir),
+            None => self.get_output_dir(ctx).join(TARGET_CACHE_DIR),
+        }
+    }
+
+    /// the folder chapters are converted to, kept between builds when the cache is on
+    pub fn get_cached_chapters_dir(&self, ctx: &mdbook::renderer::RenderContext) -> PathBuf {
+        if self.cache {
+            self.get_cache_dir(ctx).join(self.get_chapters_dir_name())
+        } else {
+            self.get_chapters_dir(ctx)
+        }
+    }
+
+    pub fn get_chapter_cache_file(&self, ctx: &mdbook::renderer::RenderContext) -> PathBuf {
+        self.get_cache_dir(ctx).join(format!("{}.json", self.get_chapters_dir_name()))
+    }
+
+    /// Hash of everything a converted chapter depends on: this version of the renderer and of its output, the chapter source,
+    /// the options used for chapters and the size and modification time of its local images.
+    pub fn get_chapter_hash(&self, chapter: &mdbook::book::Chapter, ctx: &mdbook::renderer::RenderContext) -> String {
+        let mut hasher = Sha256::new();
+        let source_path = chapter.source_path.as_deref().unwrap_or(Path::new("")).to_string_lossy();
+        let options = format!("{:?} {:?} {:?} {:?}", self.chapter_imports, self.element_functions, self.max_width, self.max_height);
+        let version = format!("{} {}", env!("CARGO_PKG_VERSION"), CONVERTER_OUTPUT_VERSION);
+        for part in [version.as_str(), &chapter.name, &source_path, &options, &chapter.content] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+
+        let src_dir = ctx.root.join(&ctx.config.book.src);
+        for image in local_image_paths(&chapter.content) {
+            if let Ok(metadata) = std::fs::metadata(src_dir.join(&image)) {
+                hasher.update(format!("{} {} {:?}", image, metadata.len(), metadata.modified().ok()).as_bytes());
+                hasher.update([0]);
+            }
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chapter_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("book.toml"), "[book]\ntitle = \"Cache\"\n").unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src").join("SUMMARY.md"), "# Summary\n").unwrap();
+        std::fs::write(dir.path().join("src").join("logo.png"), "png").unwrap();
+        let book = mdbook::MDBook::load(dir.path()).unwrap();
+        let ctx = mdbook::renderer::RenderContext::new(dir.path(), book.book.clone(), book.config.clone(), dir.path().join("book"));
+
+        let config = Config::default();
+        let mut chapter = mdbook::book::Chapter::new("Intro", "# Intro\n\n![Logo](logo.png)\n".to_string(), "intro.md", vec![]);
+        let hash = config.get_chapter_hash(&chapter, &ctx);
+        assert_eq!(hash, config.get_chapter_hash(&chapter, &ctx));
+
+        // a different image limit converts the chapter differently
+        let narrow = Config { max_width: Some(0.5), ..Config::default() };
+        assert_ne!(hash, narrow.get_chapter_hash(&chapter, &ctx));
+
+        // so does a changed image
+        std::fs::write(dir.path().join("src").join("logo.png"), "a larger png").unwrap();
+        assert_ne!(hash, config.get_chapter_hash(&chapter, &ctx));
+
+        chapter.content.push_str("\nMore text.\n");
+        let hash = config.get_chapter_hash(&chapter, &ctx);
+        let mut cache = ChapterCache::default();
+        cache.chapters.insert("intro.typ".to_string(), hash.clone());
+        let manifest = config.get_chapter_cache_file(&ctx);
+        cache.save(&manifest).unwrap();
+        assert_eq!(manifest, dir.path().join("book").join("cache").join("chapters.json"));
+        let cache = ChapterCache::load(&manifest);
+        assert!(cache.is_fresh("intro.typ", &hash));
+        assert!(!cache.is_fresh("other.typ", &hash));
+    }
+
+    #[test]
+    fn test_prune_chapter_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("intro.typ", "#mdbook-image(\"/chapters/__images/logo.png\")");
+        write("__images/logo.png", "png");
+        write("__images/old.png", "png");
+        write("removed.typ", "= Removed");
+        write("guide/setup.typ", "= Setup");
+        write("guide/__images/remote_img_1.png", "png");
+
+        let mut cache = ChapterCache::default();
+        cache.chapters.insert("intro.typ".to_string(), "hash".to_string());
+        assert_eq!(cache.prune(dir.path(), "chapters").unwrap(), 4);
+        assert!(dir.path().join("intro.typ").is_file());
+        assert!(dir.path().join("__images/logo.png").is_file());
+        assert!(!dir.path().join("__images/old.png").exists());
+        assert!(!dir.path().join("removed.typ").exists());
+        assert!(!dir.path().join("guide").exists());
+    }
+}
@@ -1,641 +1,135 @@
-use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd, CodeBlockKind};
-use std::{cmp::PartialEq, path::{Path, PathBuf}};
-use regex;
-use lazy_static::lazy_static;
-use imagesize;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::io::Write;
-use std::time::{SystemTime, UNIX_EPOCH};
 use reqwest::blocking::Client;
 
 
-use crate::config::IMAGE_DIR;
-
+use super::assets::{copy_file, copy_tree};
+use super::cache::ChapterCache;
+use super::converter::{preprocess_img_tag, remote_image_file_name, url_hash, AssetSource, Converter};
+use super::front_matter::FrontMatter;
+use super::parameters::to_typst_value;
+use super::prelude::prelude_import;
 use super::Config;
 
-// Make TableState implement PartialEq
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum TableState {
-    None,
-    InTable,
-    InHeader,
-    InRow,
-}
-
-// Helper enum to track list types
-enum ListType {
-    Ordered(Option<u64>),
-    Unordered,
-}
-
-lazy_static! {
-    static ref RE_HTML_IMG: regex::Regex = regex::Regex::new(r#"<img[^>]*src=["']([^"']+)["']"#).unwrap();
-}
-
-// 页面尺寸常量 (A4 paper in points)
-const PAGE_WIDTH: f64 = 595.0;  // A4 width in points
-const PAGE_HEIGHT: f64 = 842.0; // A4 height in points
-// const MAX_WIDTH_PERCENT: f64 = 0.95; // 95% of page width
-// const MAX_HEIGHT_PERCENT: f64 = 0.45; // 45% of page height
-
-// 计算图片的合适尺寸
-fn calculate_image_size(image_path: &str, 
-    max_width_percent: &Option<f64>, // for example, 95% of the page width
-    max_height_percent: &Option<f64>, // for example, 45% of page height
-    ctx: &mdbook::renderer::RenderContext
-) -> (String, String) {
-    // 默认值，如果无法获取图片尺寸
-    let default_width = "100%";
-    let default_height = "auto";
-
-    if max_width_percent.is_none() && max_height_percent.is_none(){
-        // return with default value if both are none
-        return (default_width.to_string(), default_height.to_string());
-    }
-
-    let mut max_width_percent = match max_width_percent{
-        Some(num)=>*num,
-        None=> 1.0
-    };
-    let mut max_height_percent = match max_height_percent{
-        Some(num) => *num,
-        None => 1.0
-    };
-    if max_width_percent>1.0 || max_width_percent<=0.0 {
-        log::error!("Image max width percent is out of range:{}, change it to 1.0 or 100%",max_width_percent);
-        max_width_percent = 1.0;
-    }
-    if max_height_percent>1.0 || max_height_percent<=0.0{
-        log::error!("Image max height percent is out of range:{}, change it to 1.0 or 100%",max_height_percent);
-        max_height_percent = 1.0;
-    }
-    
-    // 获取图片的完整路径
-    let src_dir = ctx.root.join(&ctx.config.book.src);
-    let full_path = src_dir.join(image_path);
-    
-    // 尝试获取图片尺寸
-    match imagesize::size(full_path) {
-        Ok(size) => {
-            let img_width = size.width as f64;
-            let img_height = size.height as f64;
-            
-            // 计算图片在100%宽度时的高度比例
-            let height_ratio = img_height / img_width;
-            let full_width_height = PAGE_WIDTH * height_ratio;
-            
-            // 检查规则1：如果高度超过页面高度的45%
-            if full_width_height > PAGE_HEIGHT * max_height_percent {
-                // 需要缩小图片
-                let max_height = PAGE_HEIGHT * max_height_percent;
-                let new_width = max_height / height_ratio;
-                let width_percent = (new_width / PAGE_WIDTH) * 100.0;
-                
-                return (format!("{}%", width_percent.round()), default_height.to_string());
-            }
-            
-            // 检查规则2：如果高度低于45%但宽度超过95%
-            if img_width > PAGE_WIDTH * max_width_percent && full_width_height < PAGE_HEIGHT * max_height_percent {
-                return (format!("{}%", (max_width_percent * 100.0).round()), default_height.to_string());
-            }
-            
-            // 默认使用100%宽度
-            (default_width.to_string(), default_height.to_string())
-        },
-        Err(_) => {
-            // 如果无法获取图片尺寸，使用默认值
-            (default_width.to_string(), default_height.to_string())
-        }
-    }
-}
-
 impl Config {
     pub fn parse_chapter_content(
         &self, 
         chapter: &mdbook::book::Chapter,
         content: &str, 
+        front_matter: &FrontMatter,
         dst_file_path: &std::path::Path,
         image_parent_dir: &std::path::Path,
         ctx: &mdbook::renderer::RenderContext
     ) -> anyhow::Result<String> {
-        // Parse the chapter content from markdown to typst format
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_FOOTNOTES);
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_TASKLISTS);
-
-        // preprocess the <img> tag, example: <img src="docs/01-introduction/image-20250224001420194.png" alt="image-20250224001420194" style="zoom:50%;" />
-        // to: ![image-20250224001420194](docs/01-introduction/image-20250224001420194.png)
-        let content = preprocess_img_tag(content);
-
-        let parser = Parser::new_ext(&content, options);
         let mut typst_output = String::new();
 
-        let image_folder_name = IMAGE_DIR;
-        let image_dir = image_parent_dir.join(image_folder_name);
-        
-        // Create the image directory if it doesn't exist
-        if !image_dir.exists() {
-            std::fs::create_dir_all(&image_dir)?;
-            log::debug!("Created image directory at {:?}", image_dir);
-        }
-
-        
-
         // Calculate the correct relative path to templates
         let template_rel_path = self.calculate_relative_path_to_templates(chapter,ctx);
         log::debug!("Template relative path for {}: {}", dst_file_path.display(), template_rel_path);
+        // the prelude first, so `chapter_imports` can shadow its functions
+        typst_output.push_str(&prelude_import());
         // Add quote block setting at the beginning of the document
         if let Some(chapter_imports) = &self.chapter_imports {
             typst_output.push_str(chapter_imports);
         }
+        // custom front matter metadata, templates can read it with `query(<mdbook-chapter-metadata>)`
+        if !front_matter.metadata.is_empty() {
+            let metadata = toml::Value::Table(front_matter.metadata.clone());
+            typst_output.push_str(&format!("\n#metadata({}) <mdbook-chapter-metadata>\n", to_typst_value(&metadata)));
+        }
+        // front matter image limits take precedence over the book level ones
+        let max_width = front_matter.pdf.max_width.or(self.max_width);
+        let max_height = front_matter.pdf.max_height.or(self.max_height);
         // typst_output.push_str("#import \"@preview/gentle-clues:0.6.0\": *\n\n");
 
-        let mut list_stack = Vec::new();
-        let mut table_state = TableState::None;
-        
-        
-        
-        let mut table_columns:usize = 0;
-        
-        // Track current image caption status
-        // let mut current_image_has_caption = false;
-        let mut in_image = false;
-        
-        // Add a tracking variable at the beginning of your parse_chapter_content function
-        let mut in_strong_context = false;
-        let mut handled_bold_url = false;
+        let conversion = self.get_converter(max_width, max_height, image_parent_dir, ctx).convert(content);
+        for warning in &conversion.warnings {
+            log::warn!("{}: {}", chapter.name, warning);
+        }
+        typst_output.push_str(&conversion.typst);
+        Ok(typst_output)
+    }
 
-        let mut in_code_block = false;
-        let mut is_fenced_code_block = false;
-        let mut code_block_language = None;
-        let mut first_para_in_list_item = false; // there may be multiple paras inisde a list item.
-        
-        for event in parser {
-            log::trace!("event:{:?}",event);
-            match event {
-                Event::Start(tag) => match tag {
-                    Tag::Paragraph => {
-                        if table_state != TableState::None {
-                            // Inside a table, don't add paragraph markers
-                        } else if !list_stack.is_empty(){
-                            // inside a list, need to handle ident differently.
-                            if first_para_in_list_item {
-                                first_para_in_list_item = false;
-                            }else{
-                                // ensure new line and ident
-                                let item_ident = "  ".repeat(list_stack.len());
-                                typst_output.push_str(&format!("\n{}",item_ident));
-                            }
-                        } else {
-                            typst_output.push('\n');
-                        }
-                    }
-                    Tag::Heading { level, .. } => {
-                        typst_output.push_str("\n\n");
-                        typst_output.push_str(&format!("{} ", "=".repeat(level as usize)));
-                    }
-                    Tag::BlockQuote(_) => {
-                        // Ensure a clean start for the blockquote
-                        if !typst_output.is_empty() && !typst_output.ends_with('\n') {
-                            typst_output.push('\n');
-                        }
-                        typst_output.push_str("#quote[");
-                    }
-                    Tag::CodeBlock(kind) => {
-                        log::debug!("chapter: {:?}, Code block kind: {:?}", chapter.name, kind);
-                        in_code_block = true;
-                        if let CodeBlockKind::Fenced(lang) = kind {
-                            is_fenced_code_block = true;
-                            if !lang.is_empty() {
-                                code_block_language = Some(lang.to_string());
-                            }
-                        }
-                        
-                    }
-                    Tag::List(start) => {
-                        // Ensure we start on a new line
-                        if !typst_output.ends_with('\n') {
-                            typst_output.push('\n');
-                        }
-                        
-                        if start.is_some() {
-                            list_stack.push(ListType::Ordered(start));
-                        } else {
-                            list_stack.push(ListType::Unordered);
-                        }
-                        // The actual list markers will be added by Tag::Item
+    /// A converter for the book's markdown, calling the prelude functions.
+    /// images are copied, or downloaded, into the `__images` folder under `image_parent_dir`,
+    /// and referenced from the typst folder, the prelude loads them.
+    pub fn get_converter<'a>(
+        &self,
+        max_width: Option<f64>,
+        max_height: Option<f64>,
+        image_parent_dir: &'a std::path::Path,
+        ctx: &mdbook::renderer::RenderContext
+    ) -> Converter<'a> {
+        let image_root = self.get_image_root(image_parent_dir, ctx);
+        Converter::builder()
+            .base_dir(ctx.root.join(&ctx.config.book.src))
+            .max_width(max_width)
+            .max_height(max_height)
+            .element_functions(self.get_element_functions())
+            .image_resolver(move |url, options| {
+                let mut image = options.resolve_image(url);
+                let Some(asset) = &image.asset else { return Ok(image) };
+                let target = image_parent_dir.join(&asset.path);
+                // get the path of the target, create the directory if it doesn't exist
+                if let Some(parent) = target.parent() {
+                    if !parent.exists() {
+                        log::debug!("Creating image dir: {:?}", parent);
+                        std::fs::create_dir_all(parent)?;
                     }
-                    Tag::Item => {
-                        first_para_in_list_item=true;
-                        // For all list items, we need to ensure proper formatting
-                        // Determine the list type for Typst syntax
-                        let list_type = match list_stack.last() {
-                            Some(ListType::Ordered(start)) => match start{
-                                None=> "+ ",
-                                Some(1) => "+ ", // we can't differentiate 1 or default.
-                                Some(num)=> &format!("{}. ",num),
-                            },
-                            _ => "- ",
-                        };
-                        
-                        // Add indent based on list nesting level
-                        let indent = "  ".repeat(list_stack.len() - 1);
-                        
-                        // Ensure the item starts on a new line
-                        if !typst_output.ends_with('\n') {
-                            typst_output.push('\n');
+                }
+                match &asset.source {
+                    AssetSource::File(source) if source.exists() => {
+                        // unchanged images from a previous build are left alone
+                        match copy_file(source, &target) {
+                            Err(e) => log::error!("Failed to copy image from {:?} to {:?}: {}", source, target, e),
+                            Ok(true) => log::debug!("Copied image from {:?} to {:?}", source, target),
+                            Ok(false) => log::debug!("Image {:?} is unchanged", target),
                         }
-                        
-                        typst_output.push_str(&format!("{}{} ", indent, list_type));
-                    }
-                    Tag::Emphasis => {
-                        typst_output.push_str(" _");
-                    }
-                    Tag::Strong => {
-                        in_strong_context = true;
-                        handled_bold_url = false;
-                        typst_output.push_str(" *"); // Keep this for non-URL content
                     }
-                    Tag::Strikethrough => {
-                        typst_output.push_str("#strike[");
+                    AssetSource::File(_) => {}
+                    AssetSource::Url(url) => {
+                        // a failed download is replaced by a placeholder file
+                        let image_dir = image_parent_dir.join(&options.image_dir);
+                        image.path = format!("{}/{}", options.image_dir, download_remote_image(url, &image_dir)?);
                     }
-                    Tag::Link { link_type: _, dest_url, .. } => {
-                        typst_output.push_str(&format!("#link(\"{}\")[", dest_url));
-                    }
-                    Tag::Image { link_type, dest_url, title, .. } => {
-                        // image inside a list should be indented too.
-                        let list_ident = "  ".repeat(list_stack.len());
-                        in_image = true;
-                        log::debug!("Image link_type:{:?},dest_url:{:?},title:{:?}", link_type, dest_url, title);
-                        // Extract image path
-                        let image_path_name = dest_url.to_string();
-                        
-                        
-                        // Handle local images or remote images
-                        if !image_path_name.starts_with("http://") && !image_path_name.starts_with("https://")  {
-                            let image_path = Path::new(&image_path_name);
-                            if image_path.is_absolute() {
-                                // Absolute path - use as is
-                                typst_output.push_str(&format!("{},#figure(\n  image(\"{}\"),\n  caption: none)",list_ident, image_path_name));
-                                
-                            }else{
-                                // Relative path - copy to image folder
-                                let src_dir = ctx.root.join(&ctx.config.book.src);
-                                let src_image_path = src_dir.join(&image_path_name);
-                                
-                                // Get the filename from the image path
-                                // let file_name = Path::new(&image_path_name).file_name().unwrap_or_default().to_str().unwrap_or_default();
-                                
-                                // Destination path in the image folder
-                                // let dst_image_path = image_dir.join(file_name);
-                                let dst_image_path = image_dir.join(&image_path_name);
+                }
+                image.path = format!("{}{}", image_root, image.path);
+                Ok(image)
+            })
+            .build()
+    }
 
-                                // get the path of the dst_image_path, create the directory if it doesn't exist
-                                if let Some(image_new_path) = dst_image_path.parent(){
-                                    if !image_new_path.exists() {
-                                        log::debug!("Creating image dir: {:?}", image_new_path);
-                                        std::fs::create_dir_all(image_new_path)?;
-                                    }
-                                }
-                                
-                                // Copy the image file
-                                if src_image_path.exists() {
-                                    if let Err(e) = std::fs::copy(&src_image_path, &dst_image_path) {
-                                        log::error!("Failed to copy image from {:?} to {:?}: {}", src_image_path, dst_image_path, e);
-                                    } else {
-                                        log::debug!("Copied image from {:?} to {:?}", src_image_path, dst_image_path);
-                                    }
-                                } else {
-                                    log::warn!("Source image not found: {:?}", src_image_path);
-                                }
-                                
-                                // Calculate image size
-                                let (width, _height) = calculate_image_size(&image_path_name,&self.max_width,&self.max_height, ctx);
-                                let new_image_path = format!("{}/{}", IMAGE_DIR, image_path_name);
-                                
-                                typst_output.push_str(&format!("{}#figure(\n  image(\"{}\", width: {}),\n  caption: none)", 
-                                    list_ident,new_image_path, width
-                                ));
-                                
+    // `/<folder>/` of `image_parent_dir` in the typst folder, chapters are converted in the cache folder
+    fn get_image_root(&self, image_parent_dir: &Path, ctx: &mdbook::renderer::RenderContext) -> String {
+        let relative = match image_parent_dir.strip_prefix(self.get_cached_chapters_dir(ctx)) {
+            Ok(relative) => Path::new(&self.get_chapters_dir_name()).join(relative),
+            Err(_) => image_parent_dir.strip_prefix(self.get_typst_dir(ctx)).map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let mut root = String::from("/");
+        for part in relative.components() {
+            root.push_str(&part.as_os_str().to_string_lossy());
+            root.push('/');
+        }
+        root
+    }
 
-                            }
-                            
-                        } else if image_path_name.starts_with("http://") || image_path_name.starts_with("https://") {
-                            // Handle remote images - download to image folder
-                            match download_remote_image(&image_path_name, &image_dir) {
-                                Ok(local_path) => {
-                                    typst_output.push_str(&format!("{}#figure(\n  image(\"{}/{}\"),\n  caption: none)", 
-                                        list_ident,image_folder_name, local_path
-                                    ));
-                                },
-                                Err(e) => {
-                                    log::error!("Failed to process remote image {}: {}", image_path_name, e);
-                                    typst_output.push_str(&format!("{}#text(fill: red)[Image download failed]",list_ident));
-                                    
-                                }
-                            }
-                        } 
-                    }
-                    Tag::Table(alignments) => {
-                        // if there is a list inside a table, or a table inside a list, or nested, the situation is not handled yet.
-                        log::debug!("Table alignments: {:?}", alignments);
-                        table_state = TableState::InTable;
-                        
-                        
-                        table_columns = alignments.len();
-                        typst_output.push_str("#table(\n");
-                        typst_output.push_str(&format!("  columns: {},\n", table_columns));
-                    }
-                    Tag::TableHead => {
-                        log::debug!("Table columns: {}", table_columns);
-                        table_state = TableState::InHeader;
-                        
-                        typst_output.push_str("  table.header(");
-                    }
-                    Tag::TableRow => {
-                        table_state = TableState::InRow;
-                        
-                    }
-                    Tag::TableCell => {
-                        typst_output.push('[');
-                    }
-                    Tag::FootnoteDefinition(_footnote_id) => {
-                        // Handle footnote definitions
-                        typst_output.push_str("#footnote[");
-                    }
-                    _ => {}
-                },
-                Event::End(end_tag) => match end_tag {
-                    TagEnd::Paragraph => {
-                        if table_state == TableState::None {
-                            typst_output.push('\n');
-                        }
-                    }
-                    TagEnd::Heading(_) => {
-                        typst_output.push('\n');
-                    }
-                    TagEnd::BlockQuote(_) => {
-                        // Simply add the closing bracket for blockquotes
-                        typst_output.push(']');
-                        if !typst_output.ends_with("]\n") {
-                            typst_output.push('\n');
-                        }
-                    }
-                    TagEnd::CodeBlock => {
-                        in_code_block = false;
-                        is_fenced_code_block = false;
-                        code_block_language = None;
-                    }
-                    TagEnd::List(_) => {
-                        list_stack.pop();
-                        typst_output.push('\n');
-                    }
-                    TagEnd::Item => {
-                        // 在列表项结束时添加换行
-                        typst_output.push('\n');
-                    }
-                    TagEnd::Emphasis => {
-                        typst_output.push_str("_ ");
-                    }
-                    TagEnd::Strong => {
-                        in_strong_context = false;
-                        if !handled_bold_url {
-                            typst_output.push_str("* "); // Only add closing asterisk if we didn't handle a URL
-                        } else {
-                            typst_output.push(' '); // Just add a space if we already closed the bold formatting
-                        }
-                    }
-                    TagEnd::Strikethrough => {
-                        typst_output.push(']');
-                    }
-                    TagEnd::Link => {
-                        typst_output.push(']');
-                    }
-                    TagEnd::Image => {
-                        // do nothing
-                        in_image = false;
-                    }
-                    TagEnd::Table => {
-                        table_state = TableState::None;
-                        
-                        typst_output.push_str(")\n");
-                    },
-                    TagEnd::TableHead => {
-                        table_state = TableState::InTable;
-                        typst_output.push_str("),\n");
-                        
-                        // // Instead of removing the trailing comma, ensure it's there but without the space
-                        // if typst_output.ends_with(", ") {
-                        //     // Keep the comma but replace the space with a newline
-                        //     typst_output.truncate(typst_output.len() - 1);
-                        //     typst_output.push('\n');
-                        // } else if !typst_output.ends_with(",\n") {
-                        //     // If there's no comma at all, add one before the newline
-                        //     typst_output.push_str(",\n");
-                        // }
-                    },
-                    TagEnd::TableRow => {
-                        table_state = TableState::InTable;
-                        
-                        
-                        // Instead of removing the trailing comma, ensure it's there but without the space
-                        if typst_output.ends_with(", ") {
-                            // Replace the space with a newline
-                            typst_output.truncate(typst_output.len() - 1);
-                            typst_output.push('\n');
-                        } else if !typst_output.ends_with(",\n") {
-                            // If there's no comma at all, add one before the newline
-                            typst_output.push_str(",\n");
-                        }
-                    },
-                    TagEnd::TableCell => {
-                        typst_output.push(']');
-                        typst_output.push_str(", ");
-                    },
-                    TagEnd::FootnoteDefinition => {
-                        typst_output.push(']');
-                    }
-                    _ => {}
-                },
-                Event::Text(text) => {
-                    if in_image {
-                        // do nothing
-                    }else if in_code_block {
-                        log::debug!("chapter: {:?}, Text: {:?}", chapter.name, text);
-                        if is_fenced_code_block {
-                            // if it's inside a list item, ident is required.
-                            let item_ident = "  ".repeat(list_stack.len());
-                            typst_output.push_str(&item_ident);
-                            if text.contains("```") {
-                                typst_output.push_str("````");
-                            }else{
-                                typst_output.push_str("```");
-                            }
-                        }else{
-                            typst_output.push_str("` ");
-                        }
-                        if let Some(language) = code_block_language.take() {
-                            typst_output.push_str(&language);
-                        }
-                        if is_fenced_code_block {
-                            typst_output.push('\n');
-                        }
-                        // typst_output.push('\n');
-                        typst_output.push_str(&text);
-                        if is_fenced_code_block {
-                            if text.contains("```") {
-                                typst_output.push_str("\n````");
-                            }else{
-                                typst_output.push_str("\n```");
-                            }
-                        }else{
-                            typst_output.push_str(" `");
-                        }
-                    }else{
-                        let text_str = text.to_string();
-                    
-                        if is_url(&text_str) && in_strong_context {
-                            // For URLs in bold context, remove previous bold marker and format properly
-                            // Check if we need to trim the previous " *" that was added
-                            if typst_output.ends_with(" *") {
-                                typst_output.truncate(typst_output.len() - 2);
-                            }
-                            
-                            // Add formatted bold link
-                            typst_output.push_str(&format!(" *#link(\"{}\")[{}]*", text_str, text_str));
-                            handled_bold_url = true; // Mark that we've handled this bold URL
-                        } else if is_url(&text_str) {
-                            // Regular URL (not in bold)
-                            typst_output.push_str(&format!("#link(\"{}\")[{}]", text_str, text_str));
-                        } else {
-                            // Regular text
-                            let escaped_text = escape_typst_special_chars(&text_str);
-                            let processed_text = fix_typst_formatting(&escaped_text);
-                            typst_output.push_str(&processed_text);
-                        }
-                    }
-                    
-                },
-                Event::Code(code) => {
-                    // if it's defined as code block, no matter fenced or not, it will be handled in Event::Text following 
-                    // the code block is opened and closed in Event::Text
-                    typst_output.push_str("` ");
-                    typst_output.push_str(&code);
-                    typst_output.push_str(" `");
-                },
-                Event::InlineMath(math) => {
-                    // Handle inline math with Typst's $ syntax
-                    typst_output.push_str(&format!("${{{}}};$", math));
-                },
-                Event::DisplayMath(math) => {
-                    // Handle display math with Typst's $$ syntax
-                    typst_output.push_str(&format!("$${{{}}};$$", math));
-                },
-                Event::InlineHtml(html) => {
-                    // Handle inline HTML similar to regular HTML
-                    let html_str = html.to_string();
-                    log::debug!("Inline HTML: {:?}", html_str);
-                    // Check if it's an image tag
-                    if html_str.contains("<img") {
-                        if let Some(cap) = RE_HTML_IMG.captures(&html_str) {
-                            if let Some(src) = cap.get(1) {
-                                let image_path = src.as_str();
-                                log::debug!("Inline image path: {:?}", image_path);
-                                
-                                if !image_path.starts_with("http://") && !image_path.starts_with("https://") {
-                                    // Relative path
-                                    let file_name = Path::new(image_path).file_name().unwrap_or_default().to_str().unwrap_or_default();
-                                    
-                                    // Calculate image size
-                                    let (width, _height) = calculate_image_size(image_path,&self.max_width,&self.max_height, ctx);
-                                    
-                                    typst_output.push_str(&format!("#figure(\n  image(\"{}/{}\", width: {}),\n  caption: []\n)", 
-                                        image_folder_name, file_name, width
-                                    ));
-                                } else {
-                                    // Process remote image
-                                    match download_remote_image(image_path, &image_dir) {
-                                        Ok(local_path) => {
-                                            typst_output.push_str(&format!("#figure(\n  image(\"{}/{}\"),\n  caption: []\n)", 
-                                                image_folder_name, local_path
-                                            ));
-                                        },
-                                        Err(e) => {
-                                            log::error!("Failed to process remote image {}: {}", image_path, e);
-                                            typst_output.push_str("#text(fill: red)[Image download failed]");
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    // Ignore other HTML tags
-                },
-                Event::Html(html) => {
-                    // Handle block HTML
-                    let html_str = html.to_string();
-                    
-                    // Check if it's an image tag
-                    if html_str.contains("<img") {
-                        if let Some(cap) = RE_HTML_IMG.captures(&html_str) {
-                            if let Some(src) = cap.get(1) {
-                                let image_path = src.as_str();
-                                
-                                if !image_path.starts_with("http://") && !image_path.starts_with("https://") {
-                                    // Relative path
-                                    let file_name = Path::new(image_path).file_name().unwrap_or_default().to_str().unwrap_or_default();
-                                    
-                                    // Calculate image size
-                                    let (width, _height) = calculate_image_size(image_path, &self.max_width,&self.max_height,ctx);
-                                    
-                                    typst_output.push_str(&format!("#figure(\n  image(\"{}/{}\", width: {}),\n  caption: []\n)", 
-                                        image_folder_name, file_name, width
-                                    ));
-                                } else {
-                                    // Process remote image
-                                    match download_remote_image(image_path, &image_dir) {
-                                        Ok(local_path) => {
-                                            typst_output.push_str(&format!("#figure(\n  image(\"{}/{}\"),\n  caption: []\n)", 
-                                                image_folder_name, local_path
-                                            ));
-                                        },
-                                        Err(e) => {
-                                            log::error!("Failed to process remote image {}: {}", image_path, e);
-                                            typst_output.push_str("#text(fill: red)[Image download failed]");
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    // Ignore other HTML tags
-                },
-                Event::FootnoteReference(reference) => {
-                    typst_output.push_str(&format!("#footnote[See note {}]", reference));
-                },
-                Event::SoftBreak => {
-                    typst_output.push(' ');
-                },
-                Event::HardBreak => {
-                    typst_output.push_str("\\\n");
-                },
-                Event::Rule => {
-                    typst_output.push_str("\n#line(length: 100%)\n");
-                },
-                Event::TaskListMarker(checked) => {
-                    let marker = if checked { "[x]" } else { "[ ]" };
-                    typst_output.push_str(&format!("{} ", marker));
-                },
-            }
+    /// Convert markdown to typst markup, logging the problems found.
+    /// images are copied into the `__images` folder under `image_parent_dir`.
+    pub fn convert_markdown(
+        &self,
+        content: &str,
+        max_width: Option<f64>,
+        max_height: Option<f64>,
+        image_parent_dir: &std::path::Path,
+        ctx: &mdbook::renderer::RenderContext
+    ) -> String {
+        let conversion = self.get_converter(max_width, max_height, image_parent_dir, ctx).convert(content);
+        for warning in &conversion.warnings {
+            log::warn!("{}", warning);
         }
-        
-        // Apply post-processing for special typst formatting issues
-        // let typst_output = post_process_typst_output(&typst_output)?;
-        
-        log::debug!("Converted content to typst format");
-        Ok(typst_output)
+        conversion.typst
     }
     // Helper function to debug book structure
     fn debug_book_structure(&self, book: &mdbook::book::Book) {
@@ -671,28 +165,39 @@ impl Config {
         
         // Debug book structure
         self.debug_book_structure(book);
-        
-        // // Create a map to track which chapter each image belongs to
-        // let mut chapter_images = std::collections::HashMap::new();
-        
-        for (chapter_number, item) in book.sections.iter().enumerate() {
-            match item {
-                mdbook::BookItem::Chapter(chapter) => {
-                    
-                    self.process_each_chapter(chapter, chapter_number+1, chapter_file_list, ctx)?;
-                },
-                mdbook::BookItem::Separator => {
-                    log::debug!("Skipping separator in book structure");
-                },
-                mdbook::BookItem::PartTitle(title) => {
-                    log::info!("Skipping part title in book structure: {}", title);
-                },
+
+        let mut chapters = Vec::new();
+        collect_chapters(&book.sections, &mut chapters);
+
+        // with the cache on, chapters are converted into the cache folder and copied to the typst folder afterwards,
+        // so removing the typst folder after a build doesn't lose them
+        let work_dir = self.get_cached_chapters_dir(ctx);
+        let cache_file = self.get_chapter_cache_file(ctx);
+        let previous = if self.cache { ChapterCache::load(&cache_file) } else { ChapterCache::default() };
+
+        let results = parallel_map(&chapters, self.get_jobs(), |chapter| self.process_each_chapter(chapter, &work_dir, &previous, ctx));
+
+        let mut cache = ChapterCache::default();
+        let (mut converted, mut reused) = (0, 0);
+        for (chapter, result) in chapters.iter().zip(results) {
+            let Some((key, hash, fresh)) = result? else { continue };
+            if fresh { reused += 1 } else { converted += 1 }
+            cache.chapters.insert(key, hash);
+            if let Some(chapter_file) = self.get_chapter_full_file_name(chapter, ctx) {
+                chapter_file_list.push(chapter_file);
             }
-            
         }
-        
-        // // Copy images from the source directory to chapter-specific directories
-        // self.copy_images(ctx, &chapter_dir, &chapter_images)?;
+        log::info!("converted {} chapters, {} unchanged", converted, reused);
+
+        // the files of removed chapters and the images nothing shows anymore
+        let removed = cache.prune(&work_dir, &self.get_chapters_dir_name())?;
+        log::debug!("removed {} unused files from {}", removed, work_dir.display());
+        if self.cache {
+            cache.save(&cache_file)?;
+            let stats = copy_tree(&work_dir, &self.get_chapters_dir(ctx), None)?;
+            log::debug!("copied {} cached files to {}, {} unchanged", stats.copied, self.get_chapters_dir(ctx).display(), stats.unchanged);
+            cache.prune(&self.get_chapters_dir(ctx), &self.get_chapters_dir_name())?;
+        }
         
         Ok(())
     }
@@ -715,145 +220,97 @@ impl Config {
         
     }
 
+    // Convert one chapter into `work_dir`, unless the cached file is still fresh.
+    // returns the cache key and hash of the chapter and whether it was reused
     fn process_each_chapter(&self,
         chapter: &mdbook::book::Chapter,
-        _chapter_number: usize, // full index, including Seperator and PartTitle
-        chapter_file_list: &mut Vec<PathBuf>,
+        work_dir: &Path,
+        previous: &ChapterCache,
         ctx: &mdbook::renderer::RenderContext,
-    ) -> anyhow::Result<()> {
-        
-        let chapter_dir = self.get_chapters_dir(ctx);
-        if let Some(_source_path) = &chapter.source_path {
-            
-            let chapter_file = self.get_chapter_full_file_name(chapter, ctx).unwrap(); // when source_path is Some, the chapter_file is the full path
-            chapter_file_list.push(chapter_file.clone());
-            // get the path of the `typ_name` file and create the folder if not exists
-            let typ_dir = chapter_file.parent().unwrap_or_else(|| &chapter_dir);
-            if !typ_dir.exists() {
-                std::fs::create_dir_all(typ_dir)?;
-            }
-            
-            let typst_content = self.parse_chapter_content(chapter,&chapter.content, &chapter_file, typ_dir,ctx)?;
-                
-            // Write to file
-            std::fs::write(&chapter_file, typst_content)?;
-            log::debug!("Created typst file for {}: {}", chapter.name, chapter_file.display());
+    ) -> anyhow::Result<Option<(String, String, bool)>> {
+        let Some(source_path) = &chapter.source_path else { return Ok(None) };
+        let relative_file = source_path.with_extension("typ");
+        let chapter_file = work_dir.join(&relative_file);
+        let key = relative_file.to_string_lossy().replace('\\', "/");
+
+        let (front_matter, content) = FrontMatter::extract(&chapter.content);
+        let front_matter = front_matter.unwrap_or_default();
+        for option in FrontMatter::unknown_pdf_options(&chapter.content) {
+            log::warn!("{}: unknown front matter option pdf.{} is ignored", source_path.display(), option);
+        }
+        if front_matter.pdf.skip {
+            log::info!("Skipping chapter {} as requested by its front matter", chapter.name);
+            return Ok(None);
+        }
 
+        let hash = self.get_chapter_hash(chapter, ctx);
+        if self.cache && previous.is_fresh(&key, &hash) && chapter_file.is_file() {
+            log::debug!("Reusing cached typst file for {}: {}", chapter.name, chapter_file.display());
+            return Ok(Some((key, hash, true)));
         }
 
-        for (sub_chapter_number, sub_item) in chapter.sub_items.iter().enumerate() {
-            match sub_item {
-                mdbook::BookItem::Chapter(chapter) => {
-                    self.process_each_chapter(chapter, sub_chapter_number+1, chapter_file_list, ctx)?;
-                },
-                mdbook::BookItem::Separator =>  {
-                    log::debug!("Skipping separator in book structure");
-                },
-                mdbook::BookItem::PartTitle(title) => {
-                    log::info!("Skipping part title in book structure: {}", title);
-                },
-            }
+        // get the path of the `typ_name` file and create the folder if not exists
+        let typ_dir = chapter_file.parent().unwrap_or(work_dir);
+        if !typ_dir.exists() {
+            std::fs::create_dir_all(typ_dir)?;
         }
+        let typst_content = self.parse_chapter_content(chapter, content, &front_matter, &chapter_file, typ_dir,ctx)?;
+            
+        // Write to file
+        std::fs::write(&chapter_file, typst_content)?;
+        log::debug!("Created typst file for {}: {}", chapter.name, chapter_file.display());
 
-        Ok(())
+        Ok(Some((key, hash, false)))
     }
-
-    
 }
 
-fn preprocess_img_tag(content: &str) -> String {
-    // Regex to capture the src attribute from img tags
-    let re = regex::Regex::new(r#"<img[^>]*src=["']([^"']+)["'][^>]*>"#).unwrap();
-    
-    // If no img tags found, return original content
-    if !re.is_match(content) {
-        return content.to_string();
+// All chapters of the book, depth first in SUMMARY order
+fn collect_chapters<'a>(items: &'a [mdbook::BookItem], chapters: &mut Vec<&'a mdbook::book::Chapter>) {
+    for item in items {
+        match item {
+            mdbook::BookItem::Chapter(chapter) => {
+                chapters.push(chapter);
+                collect_chapters(&chapter.sub_items, chapters);
+            },
+            mdbook::BookItem::Separator => {
+                log::debug!("Skipping separator in book structure");
+            },
+            mdbook::BookItem::PartTitle(title) => {
+                log::info!("Skipping part title in book structure: {}", title);
+            },
+        }
     }
-
-    // Regex to extract alt attribute if it exists
-    let alt_re = regex::Regex::new(r#"alt=["']([^"']+)["']"#).unwrap();
-
-    // Replace each img tag with markdown image syntax
-    let result = re.replace_all(content, |caps: &regex::Captures| {
-        let src = caps.get(1).unwrap().as_str();
-        let img_tag = caps.get(0).unwrap().as_str();
-        
-        // Try to extract alt attribute
-        let alt = if let Some(alt_caps) = alt_re.captures(img_tag) {
-            alt_caps.get(1).unwrap().as_str().to_string()
-        } else {
-            // If alt is missing, extract filename from src path
-            let path = Path::new(src);
-            let filename = path.file_stem().unwrap_or_default().to_str().unwrap_or_default();
-            filename.to_string()
-        };
-        
-        log::debug!("Converting img tag - src: {:?}, alt: {:?}", src, alt);
-        format!("![{}]({})", alt, src)
-    });
-
-    result.into_owned()
 }
-// Helper function to escape special Typst characters
-fn escape_typst_special_chars(text: &str) -> String {
-    let mut result = String::with_capacity(text.len());
-    
-    // Skip leading # characters that might be from Markdown headings
-    let text_without_leading_hash = text.trim_start_matches('#').trim_start();
-    let text_to_process = if text_without_leading_hash.len() < text.len() {
-        text_without_leading_hash
-    } else {
-        text
-    };
-    
-    // Special case for "unquoted *" pattern in command line examples
-    if text_to_process.contains("unquoted *") {
-        return text_to_process.replace("unquoted *", "unquoted \\*");
-    }
-    
-    // Special handling for URLs
-    if is_url(text_to_process) {
-        // For URLs, don't escape any characters as they'll be handled by #link
-        return text_to_process.to_string();
-    }
-    
-    // Original character escaping logic
-    for c in text_to_process.chars() {
-        match c {
-            '#' | '*' | '_' | '`' | '$' | '{' | '}' | '[' | ']' => {
-                result.push('\\');
-                result.push(c);
-            }
-            '\\' => {
-                // Double backslashes in string literals
-                result.push('\\');
-                result.push('\\');
-            }
-            '"' => {
-                // Escape quotes in string literals
-                result.push('\\');
-                result.push('"');
-            }
-            _ => result.push(c),
+
+// Run `f` over the items on up to `jobs` threads, the results keep the order of the items
+fn parallel_map<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results: std::sync::Mutex<Vec<Option<R>>> = std::sync::Mutex::new(items.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(item) = items.get(index) else { break };
+                let result = f(item);
+                results.lock().unwrap()[index] = Some(result);
+            });
         }
-    }
-    result
+    });
+    results.into_inner().unwrap().into_iter().map(|result| result.expect("every item is processed")).collect()
 }
 
-// Helper function to fix common formatting issues in Typst output
-fn fix_typst_formatting(text: &str) -> String {
-    let mut result = text.to_string();
-    
-    // Fix spacing after colons in bold text
-    result = result.replace("*:", "* :");
-    
-    // // Fix spacing after "is" in bold text
-    // result = result.replace("*is", "* is");
-    
-    // // Fix spacing after "pod" in bold text
-    // result = result.replace("*pod", "* pod");
-    
-    result
+// The relative paths of the local images a chapter shows, for the chapter cache
+pub(crate) fn local_image_paths(content: &str) -> Vec<String> {
+    Parser::new_ext(&preprocess_img_tag(content), Options::all())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Image { dest_url, .. })
+                if !dest_url.starts_with("http://") && !dest_url.starts_with("https://") && !Path::new(dest_url.as_ref()).is_absolute() =>
+            {
+                Some(dest_url.to_string())
+            }
+            _ => None,
+        })
+        .collect()
 }
 
 // // Helper function to post-process the Typst output
@@ -1208,20 +665,14 @@ fn download_remote_image(image_url: &str, image_dir: &Path) -> anyhow::Result<St
     // Create a client for making requests
     let client = Client::new();
     
-    // Generate a unique filename based on URL hash or timestamp
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-    
-    // Extract extension from URL or default to png
-    let extension = match image_url.rsplit('.').next() {
-        Some(ext) if ["jpg", "jpeg", "png", "gif", "webp", "svg"].contains(&ext.to_lowercase().as_str()) => ext,
-        _ => "png",
-    };
-    
-    let file_name = format!("remote_img_{}.{}", timestamp, extension);
+    // the file name is derived from the URL, so repeated builds produce the same typst files
+    let url_hash = url_hash(image_url);
+    let file_name = remote_image_file_name(image_url);
     let file_path = image_dir.join(&file_name);
+    if file_path.is_file() {
+        log::debug!("Remote image {} already downloaded to {}", image_url, file_path.display());
+        return Ok(file_name);
+    }
     
     // Attempt to download the image
     match client.get(image_url).send() {
@@ -1239,29 +690,24 @@ fn download_remote_image(image_url: &str, image_dir: &Path) -> anyhow::Result<St
                     },
                     Err(e) => {
                         log::error!("Failed to read image bytes from {}: {}", image_url, e);
-                        create_placeholder_image(image_dir)
+                        create_placeholder_image(image_dir, &url_hash)
                     }
                 }
             } else {
                 log::error!("Failed to download image {}: HTTP status {}", image_url, response.status());
-                create_placeholder_image(image_dir)
+                create_placeholder_image(image_dir, &url_hash)
             }
         },
         Err(e) => {
             log::error!("Failed to download image {}: {}", image_url, e);
-            create_placeholder_image(image_dir)
+            create_placeholder_image(image_dir, &url_hash)
         }
     }
 }
 
 // Create a placeholder image when download fails
-fn create_placeholder_image(image_dir: &Path) -> anyhow::Result<String> {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-    
-    let file_name = format!("placeholder_{}.txt", timestamp);
+fn create_placeholder_image(image_dir: &Path, url_hash: &str) -> anyhow::Result<String> {
+    let file_name = format!("placeholder_{}.txt", url_hash);
     let file_path = image_dir.join(&file_name);
     
     // Create a simple text file that Typst can include
@@ -1272,41 +718,171 @@ fn create_placeholder_image(image_dir: &Path) -> anyhow::Result<String> {
     Ok(file_name)
 }
 
-// Add this function to detect URLs
-fn is_url(text: &str) -> bool {
-    // Simple check for http/https URLs
-    text.starts_with("http://") || text.starts_with("https://")
-}
-
-// tests
 #[cfg(test)]
 mod tests {
+    use std::collections::{HashMap, HashSet};
+
     use super::*;
+    use crate::config::converter::{markdown_options, HeadingAttributes};
+    use proptest::prelude::*;
+    use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+    use typst_syntax::{SyntaxKind, SyntaxNode};
+
+    // words with every character that is special somewhere in Typst markup, and the newlines only Typst has
+    fn text() -> impl Strategy<Value = String> {
+        "[a-z0-9 #*_`$\\[\\]<>@~=+/\\\\.,:;()!?|\"'{}\\x0C\\x{2028}-]{1,12}"
+    }
+
+    fn inline() -> impl Strategy<Value = String> {
+        let leaf = prop_oneof![
+            4 => text(),
+            1 => text().prop_map(|text| format!("`{}`", text)),
+            1 => (text(), text()).prop_map(|(text, url)| format!("[{}]({})", text, url.replace([' ', '(', ')'], ""))),
+            1 => (text(), "[a-z]{1,6}").prop_map(|(alt, name)| format!("![{}]({}.png)", alt, name)),
+            1 => Just("https://example.com/a_b".to_string()),
+        ];
+        leaf.prop_recursive(3, 16, 4, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|text| format!("*{}*", text)),
+                inner.clone().prop_map(|text| format!("**{}**", text)),
+                inner.clone().prop_map(|text| format!("~~{}~~", text)),
+                prop::collection::vec(inner, 1..4).prop_map(|parts| parts.concat()),
+            ]
+        })
+    }
 
-    #[test]
-    fn test_process_img_tag() {
-        let input = r###"<img src="docs/01-introduction/image-20250224001420194.png" alt="image-20250224001420194" style="zoom:50%;" />"###;
-        let expected = r###"![image-20250224001420194](docs/01-introduction/image-20250224001420194.png)"###;
-        let output = preprocess_img_tag(input);
-        assert_eq!(output, expected);
+    fn line() -> impl Strategy<Value = String> {
+        prop::collection::vec(inline(), 1..4).prop_map(|parts| parts.join(" ").replace('\n', " "))
+    }
 
-        let input = r###"## Work Folder and Common OS Environment Variables
+    // a line starting with what's a heading, list, enum or term marker in Typst, escaped where it's one in markdown
+    fn marked_line() -> impl Strategy<Value = String> {
+        (prop::sample::select(vec!["=", "==", "\\-", "\\+", "1\\.", "/"]), line()).prop_map(|(marker, text)| format!("{} {}", marker, text))
+    }
 
-<img src="docs/01-introduction/image-20250221105725169.png" alt="image-20250221105725169" style="zoom:50%;" />
+    fn cell() -> impl Strategy<Value = String> {
+        prop_oneof![line(), marked_line()]
+    }
 
-Please download the attached small zip file and unzip it to a **working folder** you created. Under the working folder, you should see several components:"###;
-        let expected = r###"## Work Folder and Common OS Environment Variables
+    fn block() -> impl Strategy<Value = String> {
+        let leaf = prop_oneof![
+            4 => prop::collection::vec(line(), 1..3).prop_map(|lines| lines.join("\n")),
+            2 => marked_line(),
+            1 => (1..7usize, line()).prop_map(|(level, text)| format!("{} {}", "#".repeat(level), text)),
+            1 => ("[a-z+,]{0,8}", text()).prop_map(|(lang, code)| format!("```{}\n{}\n```", lang, code)),
+            1 => text().prop_map(|code| format!("    {}", code)),
+            1 => prop::collection::vec((cell(), cell()), 1..3).prop_map(|rows| {
+                let cells = |(a, b): &(String, String)| format!("| {} | {} |", a.replace('|', "\\|"), b.replace('|', "\\|"));
+                let body: Vec<String> = rows.iter().map(cells).collect();
+                format!("| a | b |\n|---|---|\n{}", body.join("\n"))
+            }),
+            1 => Just("---".to_string()),
+            1 => line().prop_map(|text| format!("- [x] {}", text)),
+        ];
+        leaf.prop_recursive(3, 12, 3, |inner| {
+            let indent = |block: &str, first: &str, rest: &str| {
+                block.lines().enumerate().map(|(i, line)| format!("{}{}", if i == 0 { first } else { rest }, line)).collect::<Vec<_>>().join("\n")
+            };
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 1..3).prop_map(move |items| items.iter().map(|item| indent(item, "- ", "  ")).collect::<Vec<_>>().join("\n")),
+                prop::collection::vec(inner.clone(), 1..3).prop_map(move |items| items.iter().map(|item| indent(item, "1. ", "   ")).collect::<Vec<_>>().join("\n")),
+                inner.prop_map(move |block| indent(&block, "> ", "> ")),
+            ]
+        })
+    }
+
+    fn markdown() -> impl Strategy<Value = String> {
+        prop::collection::vec(block(), 1..6).prop_map(|blocks| blocks.join("\n\n"))
+    }
 
-![image-20250221105725169](docs/01-introduction/image-20250221105725169.png)
+    // the nodes only the markdown structure makes, never its text
+    const STRUCTURE: [SyntaxKind; 8] = [
+        SyntaxKind::Heading,
+        SyntaxKind::ListItem,
+        SyntaxKind::EnumItem,
+        SyntaxKind::TermItem,
+        SyntaxKind::Ref,
+        SyntaxKind::LineComment,
+        SyntaxKind::BlockComment,
+        SyntaxKind::Equation,
+    ];
+
+    // how many of the STRUCTURE nodes the markdown should convert to, and the labels of its headings
+    fn expected_structure(content: &str) -> (HashMap<SyntaxKind, usize>, HashSet<String>) {
+        let mut counts = HashMap::new();
+        let mut labels = HashSet::new();
+        let mut ordered = Vec::new();
+        for event in Parser::new_ext(content, markdown_options()) {
+            match event {
+                Event::Start(Tag::Heading { level, id, classes, .. }) => {
+                    let heading = HeadingAttributes::new(level as usize, id.as_deref(), &classes);
+                    // these are written with the heading function
+                    if !heading.unnumbered && !heading.unlisted {
+                        *counts.entry(SyntaxKind::Heading).or_default() += 1;
+                    }
+                    labels.extend(heading.label.iter().map(|label| format!("<{}>", label)));
+                    labels.extend(heading.classes.iter().map(|class| format!("<mdbook-class-{}>", class)));
+                }
+                Event::Start(Tag::List(start)) => ordered.push(start.is_some()),
+                Event::End(TagEnd::List(_)) => {
+                    ordered.pop();
+                }
+                Event::Start(Tag::Item) => {
+                    let kind = if ordered.last() == Some(&true) { SyntaxKind::EnumItem } else { SyntaxKind::ListItem };
+                    *counts.entry(kind).or_default() += 1;
+                }
+                _ => {}
+            }
+        }
+        (counts, labels)
+    }
 
-Please download the attached small zip file and unzip it to a **working folder** you created. Under the working folder, you should see several components:"###;
-        let output = preprocess_img_tag(input);
-        assert_eq!(output, expected);
+    // the STRUCTURE nodes and the labels in markup, the ones in arguments are link targets
+    fn found_structure(node: &SyntaxNode, in_arguments: bool, counts: &mut HashMap<SyntaxKind, usize>, labels: &mut Vec<String>) {
+        match node.kind() {
+            SyntaxKind::Label if !in_arguments => labels.push(node.text().to_string()),
+            kind if STRUCTURE.contains(&kind) => *counts.entry(kind).or_default() += 1,
+            _ => {}
+        }
+        for child in node.children() {
+            found_structure(child, node.kind() == SyntaxKind::Args, counts, labels);
+        }
+    }
 
-        // <img src="_images/image-20250213001741756.png" style="zoom:50%;" />
-        let input = r###"<img src="_images/image-20250213001741756.png" style="zoom:50%;" />"###;
-        let expected = r###"![image-20250213001741756](_images/image-20250213001741756.png)"###;
-        let output = preprocess_img_tag(input);
-        assert_eq!(output, expected);
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        // whatever the markdown, the chapter is valid Typst and its text stays text
+        #[test]
+        fn test_parse_chapter_content(content in markdown()) {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(dir.path().join("book.toml"), "[book]\ntitle = \"Syntax\"\n").unwrap();
+            std::fs::create_dir(dir.path().join("src")).unwrap();
+            std::fs::write(dir.path().join("src").join("SUMMARY.md"), "# Summary\n").unwrap();
+            let book = mdbook::MDBook::load(dir.path()).unwrap();
+            let ctx = mdbook::renderer::RenderContext::new(dir.path(), book.book.clone(), book.config.clone(), dir.path().join("book"));
+
+            let config = Config::default();
+            let chapter = mdbook::book::Chapter::new("Random", content.clone(), "random.md", vec![]);
+            let chapters_dir = config.get_chapters_dir(&ctx);
+            let typst = config
+                .parse_chapter_content(&chapter, &content, &FrontMatter::default(), &chapters_dir.join("random.typ"), &chapters_dir, &ctx)
+                .unwrap();
+            let root = typst_syntax::parse(&typst);
+            let errors = root.errors();
+            prop_assert!(errors.is_empty(), "{:?}\n{}\n---\n{}", errors, content, typst);
+
+            let (expected, heading_labels) = expected_structure(&content);
+            let mut found = HashMap::new();
+            let mut labels = Vec::new();
+            found_structure(&root, false, &mut found, &mut labels);
+            for kind in STRUCTURE {
+                let count = |counts: &HashMap<SyntaxKind, usize>| counts.get(&kind).copied().unwrap_or_default();
+                prop_assert_eq!(count(&found), count(&expected), "{:?} nodes in\n{}\n---\n{}", kind, content, typst);
+            }
+            for label in labels {
+                prop_assert!(heading_labels.contains(&label), "label {} in\n{}\n---\n{}", label, content, typst);
+            }
+        }
     }
-}
\ No newline at end of file
+}
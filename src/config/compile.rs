@@ -0,0 +1,248 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::Arc,
+};
+
+use lazy_static::lazy_static;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use super::{output::OutputFormat, Config, TARGET_CHAPTERS_DIR, TARGET_TEMPLATE_DIR};
+
+lazy_static! {
+    // `chapters/intro.typ:3:5` in typst diagnostics
+    static ref RE_TYPST_LOCATION: regex::Regex = regex::Regex::new(r"([^\s:]+\.typ):(\d+):(\d+)").unwrap();
+}
+
+/// One `typst compile` run, a document to one format
+pub struct TypstJob {
+    pub typst_file: PathBuf,
+    pub output_file: PathBuf,
+    pub format: OutputFormat,
+}
+
+// Run the commands, at most `jobs` at a time, the outputs come back in the order of `commands`
+async fn run_commands(commands: Vec<Command>, jobs: usize) -> Vec<std::io::Result<Output>> {
+    let semaphore = Arc::new(Semaphore::new(jobs));
+    let mut tasks = JoinSet::new();
+    for (index, command) in commands.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            // the semaphore is never closed
+            let _permit = semaphore.acquire_owned().await.expect("semaphore closed");
+            (index, tokio::process::Command::from(command).output().await)
+        });
+    }
+    let mut outputs = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        // a panicking task is a bug, not a failed compilation
+        outputs.push(result.expect("typst compile task panicked"));
+    }
+    outputs.sort_by_key(|(index, _)| *index);
+    outputs.into_iter().map(|(_, output)| output).collect()
+}
+
+impl Config {
+    /// `jobs`, or the number of CPUs when not set
+    pub fn get_jobs(&self) -> usize {
+        match self.jobs {
+            Some(jobs) if jobs > 0 => jobs,
+            _ => std::thread::available_parallelism().map(|jobs| jobs.get()).unwrap_or(1),
+        }
+    }
+
+    /// The `typst compile` runs for a book level document, one per format in `formats`
+    pub fn get_typst_jobs(&self, ctx: &mdbook::renderer::RenderContext, document_name: &str) -> anyhow::Result<Vec<(TypstJob, Command)>> {
+        let typst_dir = self.get_typst_dir(ctx);
+        let typst_file = typst_dir.join(format!("{}.typ", document_name));
+        if !typst_file.exists() || !typst_file.is_file() {
+            return Err(anyhow::anyhow!("typst file {} not found", typst_file.display()));
+        }
+        let mut result = Vec::new();
+        for format in &self.formats {
+            let output_dir = self.get_output_dir(ctx).join(format.dir_name());
+            // create the output dir if it doesn't exist
+            if !output_dir.exists() {
+                std::fs::create_dir_all(&output_dir)?;
+            }
+            let output_file = output_dir.join(format.file_name(document_name));
+            // typst reads SOURCE_DATE_EPOCH for the pdf creation date itself
+            let mut command = Command::new("typst");
+            if let Some(epoch) = self.source_date_epoch {
+                command.env("SOURCE_DATE_EPOCH", epoch.to_string());
+            }
+            command.arg("compile").arg("--format").arg(format.extension());
+            // only pass the package folders that are configured, typst knows its defaults
+            if self.package_path.is_some() {
+                if let Some(path) = self.get_package_path(ctx) {
+                    command.arg("--package-path").arg(path);
+                }
+            }
+            if self.package_cache_path.is_some() {
+                if let Some(path) = self.get_package_cache_path(ctx) {
+                    command.arg("--package-cache-path").arg(path);
+                }
+            }
+            for path in self.get_font_paths(ctx) {
+                command.arg("--font-path").arg(path);
+            }
+            if self.ignore_system_fonts {
+                command.arg("--ignore-system-fonts");
+            }
+            match format {
+                OutputFormat::Pdf => {
+                    if let Some(standard) = &self.pdf_standard {
+                        command.arg("--pdf-standard").arg(standard);
+                    }
+                }
+                OutputFormat::Png => {
+                    if let Some(ppi) = self.ppi {
+                        command.arg("--ppi").arg(ppi.to_string());
+                    }
+                }
+                OutputFormat::Svg => {}
+            }
+            command.arg(&typst_file).arg(&output_file);
+            result.push((TypstJob { typst_file: typst_file.clone(), output_file, format: *format }, command));
+        }
+        Ok(result)
+    }
+
+    /// Compile the documents to all `formats`, `jobs` at a time.
+    /// Every failure is collected and reported together once all runs are done.
+    pub fn convert_book_to_pdf(&self, documents: &[String], ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+        let mut typst_jobs = Vec::new();
+        let mut commands = Vec::new();
+        for document in documents {
+            for (job, command) in self.get_typst_jobs(ctx, document)? {
+                typst_jobs.push(job);
+                commands.push(command);
+            }
+        }
+        let jobs = self.get_jobs();
+        log::debug!("compiling {} typst jobs, {} at a time", typst_jobs.len(), jobs);
+
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(jobs).enable_all().build()?;
+        let outputs = runtime.block_on(run_commands(commands, jobs));
+
+        let mut failures = Vec::new();
+        for (job, output) in typst_jobs.iter().zip(outputs) {
+            let output = match output {
+                Ok(output) => output,
+                Err(e) => {
+                    failures.push(format!("{}: failed to run typst: {}", job.typst_file.display(), e));
+                    continue;
+                }
+            };
+            // the runs are parallel, their messages are only printed once they're done
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            let stderr = self.map_diagnostics(&stderr, ctx);
+            if !output.status.success() {
+                failures.push(format!("{} to {}:\n{}", job.typst_file.display(), job.format.extension(), stderr));
+                continue;
+            }
+            if !stderr.is_empty() {
+                log::warn!("{}:\n{}", job.typst_file.display(), stderr);
+            }
+            log::info!("converted typst file:{} to {}:{}", job.typst_file.display(), job.format.extension(), job.output_file.display());
+            // the font report is informational, it doesn't stop the other results from being reported
+            if job.format == OutputFormat::Pdf {
+                if let Err(e) = self.report_pdf_fonts(&job.output_file) {
+                    log::warn!("failed to read the fonts of {}: {}", job.output_file.display(), e);
+                }
+            }
+        }
+        if !failures.is_empty() {
+            return Err(anyhow::anyhow!("{} typst compilations failed:\n\n{}", failures.len(), failures.join("\n\n")));
+        }
+        self.report_missing_glyphs(ctx);
+        Ok(())
+    }
+
+    /// Point the generated files in typst diagnostics back to what they were made from:
+    /// chapters to their markdown file, templates to the file in `template_dir`.
+    pub fn map_diagnostics(&self, diagnostics: &str, ctx: &mdbook::renderer::RenderContext) -> String {
+        RE_TYPST_LOCATION
+            .replace_all(diagnostics, |caps: &regex::Captures| match self.get_diagnostic_source(&caps[1], ctx) {
+                Some(source) => format!("{} (from {})", &caps[0], source.display()),
+                None => caps[0].to_string(),
+            })
+            .into_owned()
+    }
+
+    // the markdown or template file a typst file in the typst folder was generated or copied from
+    fn get_diagnostic_source(&self, typst_file: &str, ctx: &mdbook::renderer::RenderContext) -> Option<PathBuf> {
+        // typst reports files relative to the working directory
+        let typst_dir = self.get_typst_dir(ctx);
+        let path = Path::new(typst_file);
+        let absolute = std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf());
+        let path = absolute.strip_prefix(&typst_dir).unwrap_or(path);
+        let mut components = path.components();
+        let first = components.next()?.as_os_str().to_string_lossy().to_string();
+        let rest = components.as_path();
+
+        if first == TARGET_CHAPTERS_DIR || first.starts_with(&format!("{}-", TARGET_CHAPTERS_DIR)) {
+            return ctx.book.iter().find_map(|item| match item {
+                mdbook::BookItem::Chapter(mdbook::book::Chapter { source_path: Some(source_path), .. })
+                    if source_path.with_extension("typ") == rest =>
+                {
+                    Some(Path::new(&ctx.config.book.src).join(source_path))
+                }
+                _ => None,
+            });
+        }
+        if first == TARGET_TEMPLATE_DIR {
+            let template_dir = self.get_template_dir(ctx);
+            let mut components = rest.components();
+            let name = components.next()?.as_os_str().to_string_lossy().to_string();
+            let source = self
+                .templates
+                .values()
+                .find(|template| template.builtin().is_none() && template.target_file_name().is_ok_and(|file_name| file_name == name))
+                .map(|template| template_dir.join(template.file()))
+                .unwrap_or_else(|| template_dir.join(&name))
+                .join(components.as_path());
+            return source.exists().then(|| source.strip_prefix(&ctx.root).map(Path::to_path_buf).unwrap_or(source));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_commands() {
+        // the first commands finish last, `sh`, `sleep` and `false` need a unix system
+        let command = |text: &str, delay: &str| {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(format!("sleep {}; echo {}", delay, text));
+            command
+        };
+        let commands = vec![command("a", "0.3"), command("b", "0.1"), command("c", "0"), Command::new("false")];
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        let outputs = runtime.block_on(run_commands(commands, 2));
+        let stdout: Vec<String> = outputs.iter().map(|output| String::from_utf8_lossy(&output.as_ref().unwrap().stdout).trim().to_string()).collect();
+        assert_eq!(stdout, vec!["a", "b", "c", ""]);
+        assert!(!outputs[3].as_ref().unwrap().status.success());
+    }
+
+    #[test]
+    fn test_map_diagnostics() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("book.toml"), "[book]\ntitle = \"Diagnostics\"\n").unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src").join("SUMMARY.md"), "# Summary\n\n- [Intro](guide/intro.md)\n").unwrap();
+        let book = mdbook::MDBook::load(dir.path()).unwrap();
+        let ctx = mdbook::renderer::RenderContext::new(dir.path(), book.book, book.config, dir.path().join("book"));
+
+        let config = Config::default();
+        let chapter = config.get_typst_dir(&ctx).join("chapters").join("guide").join("intro.typ");
+        let diagnostics = format!("error: unknown variable: foo\n  ┌─ {}:3:5\n  ┌─ {}/book.typ:1:9", chapter.display(), config.get_typst_dir(&ctx).display());
+        let mapped = config.map_diagnostics(&diagnostics, &ctx);
+        assert!(mapped.contains(&format!("intro.typ:3:5 (from {})", Path::new("src").join("guide").join("intro.md").display())));
+        assert!(mapped.ends_with("book.typ:1:9"));
+    }
+}
@@ -0,0 +1,1026 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use sha2::{Digest, Sha256};
+
+use super::{
+    elements::{CodeBlock, DefaultElements, Element, ElementHandler, FunctionElements, ImageElement},
+    escape::escape_markup,
+    IMAGE_DIR,
+};
+
+/// Version of the Typst the converter writes, part of the chapter cache hash.
+/// Bump it with every change to the converted output, so cached chapters are converted again.
+pub const CONVERTER_OUTPUT_VERSION: u32 = 5;
+
+/// Options of a [`Converter`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConverterOptions {
+    /// local image paths are relative to this folder
+    pub base_dir: PathBuf,
+
+    /// the folder, relative to the Typst output, images are referenced from
+    pub image_dir: String,
+
+    /// max_width in a floating number between 0.0 and 1.0 (include)
+    pub max_width: Option<f64>,
+
+    /// max_height in a floating number between 0.0 and 1.0 (include)
+    pub max_height: Option<f64>,
+}
+
+impl Default for ConverterOptions {
+    fn default() -> Self {
+        Self { base_dir: PathBuf::from("."), image_dir: IMAGE_DIR.to_string(), max_width: None, max_height: None }
+    }
+}
+
+/// Where the file of an [`Asset`] comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetSource {
+    File(PathBuf),
+    Url(String),
+}
+
+/// A file the Typst output refers to, the caller copies or downloads it to `path`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Asset {
+    pub source: AssetSource,
+
+    /// relative to the Typst output
+    pub path: String,
+}
+
+/// An image as it's written in the Typst output, returned by the image resolver
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedImage {
+    /// the path given to `image()`
+    pub path: String,
+
+    /// the `width` argument, like `80%`
+    pub width: Option<String>,
+
+    /// the file to put at `path`, if any
+    pub asset: Option<Asset>,
+}
+
+/// Where a markdown link points to in the Typst output
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkTarget {
+    /// `#link("url")`
+    Url(String),
+    /// `#link(<label>)`
+    Label(String),
+}
+
+/// The result of a conversion
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Conversion {
+    /// the Typst markup
+    pub typst: String,
+
+    /// the images the markup refers to
+    pub assets: Vec<Asset>,
+
+    /// problems found in the markdown, the conversion went on without them
+    pub warnings: Vec<String>,
+}
+
+type ImageResolver<'a> = Box<dyn Fn(&str, &ConverterOptions) -> anyhow::Result<ResolvedImage> + Send + Sync + 'a>;
+type LinkResolver<'a> = Box<dyn Fn(&str) -> Option<LinkTarget> + Send + Sync + 'a>;
+type Elements<'a> = Box<dyn ElementHandler + 'a>;
+
+/// Converts markdown to Typst markup.
+///
+/// ```
+/// let converter = mdbook_typstpdf::Converter::builder().max_width(Some(0.8)).build();
+/// let conversion = converter.convert("# Title\n\nSome *text*.");
+/// assert!(conversion.typst.contains("= Title"));
+/// ```
+///
+/// Images and links go through the resolvers when they're set, see [`ConverterBuilder`],
+/// and the markup of every element comes from an [`ElementHandler`].
+/// The converter doesn't write any file, the images it refers to are in [`Conversion::assets`].
+pub struct Converter<'a> {
+    options: ConverterOptions,
+    image_resolver: Option<ImageResolver<'a>>,
+    link_resolver: Option<LinkResolver<'a>>,
+    elements: Elements<'a>,
+}
+
+/// Builds a [`Converter`]
+#[derive(Default)]
+pub struct ConverterBuilder<'a> {
+    options: ConverterOptions,
+    image_resolver: Option<ImageResolver<'a>>,
+    link_resolver: Option<LinkResolver<'a>>,
+    element_handler: Option<Elements<'a>>,
+    element_functions: IndexMap<Element, String>,
+}
+
+impl<'a> ConverterBuilder<'a> {
+    pub fn options(mut self, options: ConverterOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn base_dir(mut self, base_dir: impl Into<PathBuf>) -> Self {
+        self.options.base_dir = base_dir.into();
+        self
+    }
+
+    pub fn image_dir(mut self, image_dir: impl Into<String>) -> Self {
+        self.options.image_dir = image_dir.into();
+        self
+    }
+
+    pub fn max_width(mut self, max_width: Option<f64>) -> Self {
+        self.options.max_width = max_width;
+        self
+    }
+
+    pub fn max_height(mut self, max_height: Option<f64>) -> Self {
+        self.options.max_height = max_height;
+        self
+    }
+
+    /// Called with every image URL as written in the markdown.
+    /// [`ConverterOptions::resolve_image`] is the default, an error leaves a red note in the output and a warning.
+    pub fn image_resolver(mut self, resolver: impl Fn(&str, &ConverterOptions) -> anyhow::Result<ResolvedImage> + Send + Sync + 'a) -> Self {
+        self.image_resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Called with every link destination, `None` keeps the default:
+    /// `#id` anchors to a heading of the document become label links, anything else an URL link.
+    pub fn link_resolver(mut self, resolver: impl Fn(&str) -> Option<LinkTarget> + Send + Sync + 'a) -> Self {
+        self.link_resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Writes the markup of the elements, [`DefaultElements`] by default
+    pub fn element_handler(mut self, handler: impl ElementHandler + 'a) -> Self {
+        self.element_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Typst functions called for elements instead of their handler methods, like `quote` to `mybox`
+    /// for `#mybox[...]`. The template or `chapter_imports` defines the functions.
+    pub fn element_functions(mut self, functions: IndexMap<Element, String>) -> Self {
+        self.element_functions = functions;
+        self
+    }
+
+    pub fn build(self) -> Converter<'a> {
+        let handler = self.element_handler.unwrap_or_else(|| Box::new(DefaultElements));
+        let elements: Elements<'a> = if self.element_functions.is_empty() {
+            handler
+        } else {
+            Box::new(FunctionElements { functions: self.element_functions, handler })
+        };
+        Converter { options: self.options, image_resolver: self.image_resolver, link_resolver: self.link_resolver, elements }
+    }
+}
+
+impl ConverterOptions {
+    /// The default image resolution: remote images are downloaded to `image_dir` under a name made from their URL,
+    /// relative ones are copied to the same path under `image_dir`, absolute ones are used as they are.
+    pub fn resolve_image(&self, url: &str) -> ResolvedImage {
+        if is_url(url) {
+            let path = format!("{}/{}", self.image_dir, remote_image_file_name(url));
+            return ResolvedImage { path: path.clone(), width: None, asset: Some(Asset { source: AssetSource::Url(url.to_string()), path }) };
+        }
+        if Path::new(url).is_absolute() {
+            return ResolvedImage { path: url.to_string(), width: None, asset: None };
+        }
+        let source = self.base_dir.join(url);
+        let (width, _height) = calculate_image_size(&source, &self.max_width, &self.max_height);
+        let path = format!("{}/{}", self.image_dir, url);
+        ResolvedImage { path: path.clone(), width: Some(width), asset: Some(Asset { source: AssetSource::File(source), path }) }
+    }
+}
+
+impl Converter<'_> {
+    pub fn builder<'a>() -> ConverterBuilder<'a> {
+        ConverterBuilder::default()
+    }
+
+    pub fn options(&self) -> &ConverterOptions {
+        &self.options
+    }
+
+    // the image as written in the output, `None` when it couldn't be resolved
+    fn resolve_image(&self, url: &str, conversion: &mut Conversion) -> Option<ResolvedImage> {
+        let image = match &self.image_resolver {
+            Some(resolver) => resolver(url, &self.options),
+            None => Ok(self.options.resolve_image(url)),
+        };
+        match image {
+            Ok(image) => {
+                if let Some(asset) = &image.asset {
+                    if let AssetSource::File(source) = &asset.source {
+                        if !source.exists() {
+                            conversion.warnings.push(format!("image not found: {}", source.display()));
+                        }
+                    }
+                    if !conversion.assets.contains(asset) {
+                        conversion.assets.push(asset.clone());
+                    }
+                }
+                Some(image)
+            }
+            Err(e) => {
+                conversion.warnings.push(format!("failed to process image {}: {:#}", url, e));
+                None
+            }
+        }
+    }
+
+    fn resolve_link(&self, url: &str, heading_ids: &HashSet<String>) -> LinkTarget {
+        if let Some(target) = self.link_resolver.as_ref().and_then(|resolver| resolver(url)) {
+            return target;
+        }
+        // in-page anchors pointing to an explicit heading id become label links
+        match url.strip_prefix('#').map(typst_label) {
+            Some(label) if heading_ids.contains(&label) => LinkTarget::Label(label),
+            _ => LinkTarget::Url(url.to_string()),
+        }
+    }
+
+    // the image at `url`, or a red note when it couldn't be resolved
+    fn push_image(&self, url: &str, alt: &str, conversion: &mut Conversion, typst_output: &mut String) {
+        match self.resolve_image(url, conversion) {
+            Some(image) => {
+                let image = ImageElement { path: image.path, width: image.width, alt: alt.to_string() };
+                self.elements.image(&image, typst_output);
+            }
+            None => typst_output.push_str("#text(fill: red)[Image download failed]"),
+        }
+    }
+
+    // an html `<img>` tag is an image, any other html is left out
+    fn push_html(&self, html: &str, conversion: &mut Conversion, typst_output: &mut String) {
+        let Some(src) = RE_HTML_IMG.captures(html).map(|cap| cap[1].to_string()) else { return };
+        log::debug!("Inline image path: {:?}", src);
+        let alt = RE_HTML_IMG_ALT.captures(html).map(|cap| cap[1].to_string()).unwrap_or_default();
+        self.push_image(&src, &alt, conversion, typst_output);
+    }
+
+    /// Convert markdown to Typst markup
+    pub fn convert(&self, markdown: &str) -> Conversion {
+        let mut conversion = Conversion::default();
+        // Parse the chapter content from markdown to typst format
+        let options = markdown_options();
+
+        // preprocess the <img> tag, example: <img src="docs/01-introduction/image-20250224001420194.png" alt="image-20250224001420194" style="zoom:50%;" />
+        // to: ![image-20250224001420194](docs/01-introduction/image-20250224001420194.png)
+        let content = preprocess_img_tag(markdown);
+
+        // explicit heading ids are emitted as labels, so links to them can target the label directly
+        let heading_ids = collect_heading_ids(&content, options);
+
+        let parser = Parser::new_ext(&content, options);
+        let elements = self.elements.as_ref();
+        let mut typst_output = String::new();
+
+        let mut list_stack = Vec::new();
+        let mut table_state = TableState::None;
+        
+        
+        
+        let mut table_columns:usize = 0;
+        
+        // Track current image caption status
+        // let mut current_image_has_caption = false;
+        let mut in_image = false;
+        // the alt text is only known at the end of the image, the image is written there
+        let mut image_alt = String::new();
+        let mut image_url = String::new();
+
+        // the code is only known at the end of the code block
+        let mut code_block: Option<CodeBlock> = None;
+        let mut first_para_in_list_item = false; // there may be multiple paras inisde a list item.
+        let mut current_heading: Option<HeadingAttributes> = None;
+        
+        for event in parser {
+            log::trace!("event:{:?}",event);
+            match event {
+                Event::Start(tag) => match tag {
+                    Tag::Paragraph => {
+                        if table_state != TableState::None {
+                            // Inside a table, don't add paragraph markers
+                        } else if !list_stack.is_empty(){
+                            // inside a list, need to handle ident differently.
+                            if first_para_in_list_item {
+                                first_para_in_list_item = false;
+                            }else{
+                                // ensure new line and ident
+                                let item_ident = "  ".repeat(list_stack.len());
+                                typst_output.push_str(&format!("\n{}",item_ident));
+                            }
+                        } else {
+                            typst_output.push('\n');
+                        }
+                        elements.paragraph_start(&mut typst_output);
+                    }
+                    Tag::Heading { level, id, classes, .. } => {
+                        typst_output.push_str("\n\n");
+                        let heading = HeadingAttributes::new(level as usize, id.as_deref(), &classes);
+                        elements.heading_start(&heading, &mut typst_output);
+                        current_heading = Some(heading);
+                    }
+                    Tag::BlockQuote(_) => {
+                        // Ensure a clean start for the blockquote
+                        if !typst_output.is_empty() && !typst_output.ends_with('\n') {
+                            typst_output.push('\n');
+                        }
+                        elements.quote_start(&mut typst_output);
+                    }
+                    Tag::CodeBlock(kind) => {
+                        log::debug!("Code block kind: {:?}", kind);
+                        // if it's inside a list item, ident is required.
+                        let mut block = CodeBlock { indent: "  ".repeat(list_stack.len()), ..CodeBlock::default() };
+                        if let CodeBlockKind::Fenced(lang) = kind {
+                            block.fenced = true;
+                            // the first word of the info string, like `rust` in `rust,ignore`
+                            block.language = lang.split(|c: char| c == ',' || c.is_whitespace()).next().filter(|lang| !lang.is_empty()).map(str::to_string);
+                        }
+                        code_block = Some(block);
+                    }
+                    Tag::List(start) => {
+                        // Ensure we start on a new line
+                        if !typst_output.ends_with('\n') {
+                            typst_output.push('\n');
+                        }
+                        
+                        if start.is_some() {
+                            list_stack.push(ListType::Ordered(start));
+                        } else {
+                            list_stack.push(ListType::Unordered);
+                        }
+                        // The actual list markers will be added by Tag::Item
+                    }
+                    Tag::Item => {
+                        first_para_in_list_item=true;
+                        // For all list items, we need to ensure proper formatting
+                        // Determine the list type for Typst syntax
+                        let number = match list_stack.last() {
+                            Some(ListType::Ordered(start)) => Some(start.unwrap_or(1)),
+                            _ => None,
+                        };
+                        
+                        // Add indent based on list nesting level
+                        let indent = "  ".repeat(list_stack.len() - 1);
+                        
+                        // Ensure the item starts on a new line
+                        if !typst_output.ends_with('\n') {
+                            typst_output.push('\n');
+                        }
+                        
+                        typst_output.push_str(&indent);
+                        elements.list_item_start(number, &mut typst_output);
+                    }
+                    Tag::Emphasis => {
+                        elements.emphasis_start(&mut typst_output);
+                    }
+                    Tag::Strong => {
+                        elements.strong_start(&mut typst_output);
+                    }
+                    Tag::Strikethrough => {
+                        elements.strikethrough_start(&mut typst_output);
+                    }
+                    Tag::Link { link_type: _, dest_url, .. } => {
+                        elements.link_start(&self.resolve_link(&dest_url, &heading_ids), &mut typst_output);
+                    }
+                    Tag::Image { link_type, dest_url, title, .. } => {
+                        in_image = true;
+                        log::debug!("Image link_type:{:?},dest_url:{:?},title:{:?}", link_type, dest_url, title);
+                        image_url = dest_url.to_string();
+                    }
+                    Tag::Table(alignments) => {
+                        // if there is a list inside a table, or a table inside a list, or nested, the situation is not handled yet.
+                        log::debug!("Table alignments: {:?}", alignments);
+                        table_state = TableState::InTable;
+                        
+                        
+                        table_columns = alignments.len();
+                        elements.table_start(table_columns, &mut typst_output);
+                    }
+                    Tag::TableHead => {
+                        log::debug!("Table columns: {}", table_columns);
+                        table_state = TableState::InHeader;
+                        
+                        elements.table_header_start(&mut typst_output);
+                    }
+                    Tag::TableRow => {
+                        table_state = TableState::InRow;
+                        
+                    }
+                    Tag::TableCell => {
+                        elements.table_cell_start(&mut typst_output);
+                    }
+                    Tag::FootnoteDefinition(_footnote_id) => {
+                        // Handle footnote definitions
+                        elements.footnote_start(&mut typst_output);
+                    }
+                    _ => {}
+                },
+                Event::End(end_tag) => match end_tag {
+                    TagEnd::Paragraph => {
+                        elements.paragraph_end(&mut typst_output);
+                        if table_state == TableState::None {
+                            typst_output.push('\n');
+                        }
+                    }
+                    TagEnd::Heading(_) => {
+                        if let Some(heading) = current_heading.take() {
+                            elements.heading_end(&heading, &mut typst_output);
+                        }
+                        typst_output.push('\n');
+                    }
+                    TagEnd::BlockQuote(_) => {
+                        elements.quote_end(&mut typst_output);
+                        if !typst_output.ends_with("]\n") {
+                            typst_output.push('\n');
+                        }
+                    }
+                    TagEnd::CodeBlock => {
+                        if let Some(block) = code_block.take() {
+                            elements.code_block(&block, &mut typst_output);
+                        }
+                    }
+                    TagEnd::List(_) => {
+                        list_stack.pop();
+                        typst_output.push('\n');
+                    }
+                    TagEnd::Item => {
+                        elements.list_item_end(&mut typst_output);
+                        // 在列表项结束时添加换行
+                        typst_output.push('\n');
+                    }
+                    TagEnd::Emphasis => {
+                        elements.emphasis_end(&mut typst_output);
+                    }
+                    TagEnd::Strong => {
+                        elements.strong_end(&mut typst_output);
+                    }
+                    TagEnd::Strikethrough => {
+                        elements.strikethrough_end(&mut typst_output);
+                    }
+                    TagEnd::Link => {
+                        elements.link_end(&mut typst_output);
+                    }
+                    TagEnd::Image => {
+                        in_image = false;
+                        // image inside a list should be indented too.
+                        typst_output.push_str(&"  ".repeat(list_stack.len()));
+                        self.push_image(&image_url, &image_alt, &mut conversion, &mut typst_output);
+                        image_alt.clear();
+                    }
+                    TagEnd::Table => {
+                        table_state = TableState::None;
+                        elements.table_end(&mut typst_output);
+                    },
+                    TagEnd::TableHead => {
+                        table_state = TableState::InTable;
+                        elements.table_header_end(&mut typst_output);
+                        
+                        // // Instead of removing the trailing comma, ensure it's there but without the space
+                        // if typst_output.ends_with(", ") {
+                        //     // Keep the comma but replace the space with a newline
+                        //     typst_output.truncate(typst_output.len() - 1);
+                        //     typst_output.push('\n');
+                        // } else if !typst_output.ends_with(",\n") {
+                        //     // If there's no comma at all, add one before the newline
+                        //     typst_output.push_str(",\n");
+                        // }
+                    },
+                    TagEnd::TableRow => {
+                        table_state = TableState::InTable;
+                        
+                        
+                        // Instead of removing the trailing comma, ensure it's there but without the space
+                        if typst_output.ends_with(", ") {
+                            // Replace the space with a newline
+                            typst_output.truncate(typst_output.len() - 1);
+                            typst_output.push('\n');
+                        } else if !typst_output.ends_with(",\n") {
+                            // If there's no comma at all, add one before the newline
+                            typst_output.push_str(",\n");
+                        }
+                    },
+                    TagEnd::TableCell => {
+                        elements.table_cell_end(&mut typst_output);
+                        typst_output.push_str(", ");
+                    },
+                    TagEnd::FootnoteDefinition => {
+                        elements.footnote_end(&mut typst_output);
+                    }
+                    _ => {}
+                },
+                Event::Text(text) => {
+                    if in_image {
+                        image_alt.push_str(&text);
+                    } else if let Some(block) = code_block.as_mut() {
+                        log::trace!("Code block text: {:?}", text);
+                        block.text.push_str(&text);
+                    } else if is_url(&text) {
+                        // a bare URL is a link to itself
+                        elements.link_start(&LinkTarget::Url(text.to_string()), &mut typst_output);
+                        typst_output.push_str(&escape_markup(&text, &typst_output));
+                        elements.link_end(&mut typst_output);
+                    } else {
+                        typst_output.push_str(&escape_markup(&text, &typst_output));
+                    }
+                },
+                Event::Code(code) if in_image => {
+                    image_alt.push_str(&code);
+                },
+                Event::Code(code) => {
+                    // code blocks are written at their end, their text comes as Event::Text
+                    elements.code(&code, &mut typst_output);
+                },
+                Event::InlineMath(math) => {
+                    // Handle inline math with Typst's $ syntax
+                    typst_output.push_str(&format!("${{{}}};$", math));
+                },
+                Event::DisplayMath(math) => {
+                    // Handle display math with Typst's $$ syntax
+                    typst_output.push_str(&format!("$${{{}}};$$", math));
+                },
+                Event::InlineHtml(html) => {
+                    // Handle inline HTML similar to regular HTML
+                    log::debug!("Inline HTML: {:?}", html);
+                    self.push_html(&html, &mut conversion, &mut typst_output);
+                    // Ignore other HTML tags
+                },
+                Event::Html(html) => {
+                    // Handle block HTML
+                    self.push_html(&html, &mut conversion, &mut typst_output);
+                    // Ignore other HTML tags
+                },
+                Event::FootnoteReference(reference) => {
+                    elements.footnote_reference(&reference, &mut typst_output);
+                },
+                Event::SoftBreak => {
+                    typst_output.push(' ');
+                },
+                Event::HardBreak => {
+                    typst_output.push_str("\\\n");
+                },
+                Event::Rule => {
+                    elements.rule(&mut typst_output);
+                },
+                Event::TaskListMarker(checked) => {
+                    elements.task(checked, &mut typst_output);
+                },
+            }
+        }
+        
+        log::debug!("Converted content to typst format");
+        conversion.typst = typst_output;
+        conversion
+    }
+}
+
+// Make TableState implement PartialEq
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TableState {
+    None,
+    InTable,
+    InHeader,
+    InRow,
+}
+
+// Helper enum to track list types
+enum ListType {
+    Ordered(Option<u64>),
+    Unordered,
+}
+
+/// Heading attributes from `## Title {#id .class}`.
+/// `.unnumbered` and `.unlisted` map to `numbering: none` and `outlined: false`,
+/// any other class wraps the heading in a block labelled `<mdbook-class-NAME>`
+/// so templates can style it with `show <mdbook-class-NAME>: ...`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadingAttributes {
+    pub level: usize,
+    pub label: Option<String>,
+    pub unnumbered: bool,
+    pub unlisted: bool,
+    pub classes: Vec<String>,
+}
+
+impl HeadingAttributes {
+    pub(crate) fn new(level: usize, id: Option<&str>, classes: &[pulldown_cmark::CowStr]) -> Self {
+        let mut heading = HeadingAttributes {
+            level,
+            label: id.map(typst_label).filter(|label| !label.is_empty()),
+            unnumbered: false,
+            unlisted: false,
+            classes: Vec::new(),
+        };
+        for class in classes {
+            match class.as_ref() {
+                "unnumbered" => heading.unnumbered = true,
+                "unlisted" => heading.unlisted = true,
+                other => {
+                    let class = typst_label(other);
+                    if !class.is_empty() {
+                        heading.classes.push(class);
+                    }
+                }
+            }
+        }
+        heading
+    }
+
+    // the markup form `== Title` can't carry numbering/outlined, so fall back to the function form
+    fn needs_function_form(&self) -> bool {
+        self.unnumbered || self.unlisted
+    }
+
+    /// `== ` or, when needed, `#heading(...)[`, inside the class blocks
+    pub fn push_start(&self, typst_output: &mut String) {
+        if self.needs_function_form() {
+            self.push_function_start("heading", typst_output);
+        } else {
+            for _ in &self.classes {
+                typst_output.push_str("#[");
+            }
+            typst_output.push_str(&format!("{} ", "=".repeat(self.level)));
+        }
+    }
+
+    /// the closing of [`HeadingAttributes::push_start`], with the label and the class labels
+    pub fn push_end(&self, typst_output: &mut String) {
+        if self.needs_function_form() {
+            self.push_function_end(typst_output);
+        } else {
+            self.push_labels(typst_output);
+        }
+    }
+
+    /// `#function(depth: N, ...)[`, the function takes the arguments of `heading`
+    pub fn push_function_start(&self, function: &str, typst_output: &mut String) {
+        for _ in &self.classes {
+            typst_output.push_str("#[");
+        }
+        typst_output.push_str(&format!("#{}(depth: {}", function, self.level));
+        if self.unnumbered {
+            typst_output.push_str(", numbering: none");
+        }
+        if self.unlisted {
+            typst_output.push_str(", outlined: false");
+        }
+        typst_output.push_str(")[");
+    }
+
+    pub fn push_function_end(&self, typst_output: &mut String) {
+        typst_output.push(']');
+        self.push_labels(typst_output);
+    }
+
+    fn push_labels(&self, typst_output: &mut String) {
+        if let Some(label) = &self.label {
+            typst_output.push_str(&format!(" <{}>", label));
+        }
+        for class in self.classes.iter().rev() {
+            typst_output.push_str(&format!("] <mdbook-class-{}>", class));
+        }
+    }
+}
+
+lazy_static! {
+    static ref RE_HTML_IMG: regex::Regex = regex::Regex::new(r#"<img[^>]*src=["']([^"']+)["']"#).unwrap();
+    pub(crate) static ref RE_HTML_IMG_ALT: regex::Regex = regex::Regex::new(r#"<img[^>]*alt=["']([^"']*)["']"#).unwrap();
+}
+
+// 页面尺寸常量 (A4 paper in points)
+const PAGE_WIDTH: f64 = 595.0;  // A4 width in points
+const PAGE_HEIGHT: f64 = 842.0; // A4 height in points
+// const MAX_WIDTH_PERCENT: f64 = 0.95; // 95% of page width
+// const MAX_HEIGHT_PERCENT: f64 = 0.45; // 45% of page height
+
+// 计算图片的合适尺寸
+fn calculate_image_size(full_path: &Path, 
+    max_width_percent: &Option<f64>, // for example, 95% of the page width
+    max_height_percent: &Option<f64>, // for example, 45% of page height
+) -> (String, String) {
+    // 默认值，如果无法获取图片尺寸
+    let default_width = "100%";
+    let default_height = "auto";
+
+    if max_width_percent.is_none() && max_height_percent.is_none(){
+        // return with default value if both are none
+        return (default_width.to_string(), default_height.to_string());
+    }
+
+    let mut max_width_percent = match max_width_percent{
+        Some(num)=>*num,
+        None=> 1.0
+    };
+    let mut max_height_percent = match max_height_percent{
+        Some(num) => *num,
+        None => 1.0
+    };
+    if max_width_percent>1.0 || max_width_percent<=0.0 {
+        log::error!("Image max width percent is out of range:{}, change it to 1.0 or 100%",max_width_percent);
+        max_width_percent = 1.0;
+    }
+    if max_height_percent>1.0 || max_height_percent<=0.0{
+        log::error!("Image max height percent is out of range:{}, change it to 1.0 or 100%",max_height_percent);
+        max_height_percent = 1.0;
+    }
+    
+    // 尝试获取图片尺寸
+    match imagesize::size(full_path) {
+        Ok(size) => {
+            let img_width = size.width as f64;
+            let img_height = size.height as f64;
+            
+            // 计算图片在100%宽度时的高度比例
+            let height_ratio = img_height / img_width;
+            let full_width_height = PAGE_WIDTH * height_ratio;
+            
+            // 检查规则1：如果高度超过页面高度的45%
+            if full_width_height > PAGE_HEIGHT * max_height_percent {
+                // 需要缩小图片
+                let max_height = PAGE_HEIGHT * max_height_percent;
+                let new_width = max_height / height_ratio;
+                let width_percent = (new_width / PAGE_WIDTH) * 100.0;
+                
+                return (format!("{}%", width_percent.round()), default_height.to_string());
+            }
+            
+            // 检查规则2：如果高度低于45%但宽度超过95%
+            if img_width > PAGE_WIDTH * max_width_percent && full_width_height < PAGE_HEIGHT * max_height_percent {
+                return (format!("{}%", (max_width_percent * 100.0).round()), default_height.to_string());
+            }
+            
+            // 默认使用100%宽度
+            (default_width.to_string(), default_height.to_string())
+        },
+        Err(_) => {
+            // 如果无法获取图片尺寸，使用默认值
+            (default_width.to_string(), default_height.to_string())
+        }
+    }
+}
+
+// Convert an id or class name into a valid Typst label name.
+// Typst labels only accept identifier characters plus `.` and `:`.
+fn typst_label(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':') { c } else { '-' })
+        .collect()
+}
+
+// The markdown extensions the converter reads
+pub(crate) fn markdown_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    options
+}
+
+// Collect the explicit `{#id}` attributes of all headings in a chapter
+fn collect_heading_ids(content: &str, options: Options) -> HashSet<String> {
+    Parser::new_ext(content, options)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading { id: Some(id), .. }) => Some(typst_label(&id)),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn preprocess_img_tag(content: &str) -> String {
+    // Regex to capture the src attribute from img tags
+    let re = regex::Regex::new(r#"<img[^>]*src=["']([^"']+)["'][^>]*>"#).unwrap();
+    
+    // If no img tags found, return original content
+    if !re.is_match(content) {
+        return content.to_string();
+    }
+
+    // Regex to extract alt attribute if it exists
+    let alt_re = regex::Regex::new(r#"alt=["']([^"']+)["']"#).unwrap();
+
+    // Replace each img tag with markdown image syntax
+    let result = re.replace_all(content, |caps: &regex::Captures| {
+        let src = caps.get(1).unwrap().as_str();
+        let img_tag = caps.get(0).unwrap().as_str();
+        
+        // Try to extract alt attribute
+        let alt = if let Some(alt_caps) = alt_re.captures(img_tag) {
+            alt_caps.get(1).unwrap().as_str().to_string()
+        } else {
+            // If alt is missing, extract filename from src path
+            let path = Path::new(src);
+            let filename = path.file_stem().unwrap_or_default().to_str().unwrap_or_default();
+            filename.to_string()
+        };
+        
+        log::debug!("Converting img tag - src: {:?}, alt: {:?}", src, alt);
+        format!("![{}]({})", alt, src)
+    });
+
+    result.into_owned()
+}
+// The file name of a downloaded remote image,
+// derived from the URL so repeated builds produce the same typst files
+pub(crate) fn remote_image_file_name(image_url: &str) -> String {
+    // Extract extension from URL or default to png
+    let extension = match image_url.rsplit('.').next() {
+        Some(ext) if ["jpg", "jpeg", "png", "gif", "webp", "svg"].contains(&ext.to_lowercase().as_str()) => ext,
+        _ => "png",
+    };
+    format!("remote_img_{}.{}", url_hash(image_url), extension)
+}
+
+// First 16 hex digits of the SHA-256 of the image URL
+pub(crate) fn url_hash(image_url: &str) -> String {
+    let digest = Sha256::digest(image_url.as_bytes());
+    digest.iter().take(8).map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Add this function to detect URLs
+fn is_url(text: &str) -> bool {
+    // Simple check for http/https URLs
+    text.starts_with("http://") || text.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::escape::tests::plain_text;
+
+    #[test]
+    fn test_process_img_tag() {
+        let input = r###"<img src="docs/01-introduction/image-20250224001420194.png" alt="image-20250224001420194" style="zoom:50%;" />"###;
+        let expected = r###"![image-20250224001420194](docs/01-introduction/image-20250224001420194.png)"###;
+        let output = preprocess_img_tag(input);
+        assert_eq!(output, expected);
+
+        let input = r###"## Work Folder and Common OS Environment Variables
+
+<img src="docs/01-introduction/image-20250221105725169.png" alt="image-20250221105725169" style="zoom:50%;" />
+
+Please download the attached small zip file and unzip it to a **working folder** you created. Under the working folder, you should see several components:"###;
+        let expected = r###"## Work Folder and Common OS Environment Variables
+
+![image-20250221105725169](docs/01-introduction/image-20250221105725169.png)
+
+Please download the attached small zip file and unzip it to a **working folder** you created. Under the working folder, you should see several components:"###;
+        let output = preprocess_img_tag(input);
+        assert_eq!(output, expected);
+
+        // <img src="_images/image-20250213001741756.png" style="zoom:50%;" />
+        let input = r###"<img src="_images/image-20250213001741756.png" style="zoom:50%;" />"###;
+        let expected = r###"![image-20250213001741756](_images/image-20250213001741756.png)"###;
+        let output = preprocess_img_tag(input);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_heading_attributes() {
+        let render = |heading: HeadingAttributes, title: &str| {
+            let mut output = String::new();
+            heading.push_start(&mut output);
+            output.push_str(title);
+            heading.push_end(&mut output);
+            output
+        };
+
+        let heading = HeadingAttributes::new(2, Some("example-id"), &[]);
+        assert_eq!(render(heading, "Custom id"), "== Custom id <example-id>");
+
+        let heading = HeadingAttributes::new(1, None, &["unnumbered".into(), "unlisted".into()]);
+        assert_eq!(render(heading, "Preface"), "#heading(depth: 1, numbering: none, outlined: false)[Preface]");
+
+        let heading = HeadingAttributes::new(2, Some("both id"), &["class1".into(), "class2".into()]);
+        assert_eq!(
+            render(heading, "Both"),
+            "#[#[== Both <both-id>] <mdbook-class-class2>] <mdbook-class-class1>"
+        );
+    }
+
+    #[test]
+    fn test_converter() {
+        let markdown = "# Guide {#guide}\n\n![Logo](img/logo.png)\n\nSee [the guide](#guide), [setup](setup.md) and ![Remote](https://example.com/a.svg).\n";
+        let conversion = Converter::builder().base_dir("/nowhere").max_width(Some(0.5)).build().convert(markdown);
+        assert!(conversion.typst.contains("image(\"__images/img/logo.png\", width: 100%, alt: \"Logo\")"));
+        assert!(conversion.typst.contains("#link(<guide>)[the guide]"));
+        assert!(conversion.typst.contains("#link(\"setup.md\")[setup]"));
+        assert_eq!(conversion.assets.len(), 2);
+        assert_eq!(conversion.assets[0].source, AssetSource::File(PathBuf::from("/nowhere/img/logo.png")));
+        assert_eq!(conversion.assets[1].source, AssetSource::Url("https://example.com/a.svg".to_string()));
+        assert_eq!(conversion.warnings, vec!["image not found: /nowhere/img/logo.png"]);
+
+        let converter = Converter::builder()
+            .image_resolver(|url, _options| match url {
+                "img/logo.png" => Ok(ResolvedImage { path: "static/logo.png".to_string(), width: Some("2cm".to_string()), asset: None }),
+                _ => Err(anyhow::anyhow!("remote images are not allowed")),
+            })
+            .link_resolver(|url| url.strip_suffix(".md").map(|chapter| LinkTarget::Label(format!("chapter-{}", chapter))))
+            .build();
+        let conversion = converter.convert(markdown);
+        assert!(conversion.typst.contains("image(\"static/logo.png\", width: 2cm, alt: \"Logo\")"));
+        assert!(conversion.typst.contains("#link(<chapter-setup>)[setup]"));
+        assert!(conversion.typst.contains("#text(fill: red)[Image download failed]"));
+        assert!(conversion.assets.is_empty());
+        assert_eq!(conversion.warnings, vec!["failed to process image https://example.com/a.svg: remote images are not allowed"]);
+    }
+
+    // the nodes plain markdown text must never turn into
+    fn unintended_nodes(node: &typst_syntax::SyntaxNode, found: &mut Vec<String>) {
+        use typst_syntax::SyntaxKind;
+        match node.kind() {
+            SyntaxKind::Error | SyntaxKind::LineComment | SyntaxKind::BlockComment | SyntaxKind::Ref | SyntaxKind::Equation => {
+                found.push(format!("{:?} {:?}", node.kind(), node.clone().into_text()));
+            }
+            _ => node.children().for_each(|child| unintended_nodes(child, found)),
+        }
+    }
+
+    #[test]
+    fn test_typst_syntax() {
+        let mut chapters = vec![indoc::indoc! {r#"
+            # #1 heading with *stars*: and `code`
+
+            #hashtag, unquoted * star, a_b_c, $5 and $6, [brackets], <tag>, @someone, a~b, C:\path\to
+            = not a heading
+            - not a list
+            12\. not an enum, // not a comment, /* nor this */ and "quotes"
+
+            **bold**: after, *emphasis*(parens) and [a link](https://example.com/a_b?c=d&e)(.txt)
+
+            `` a`b `` and ```` ``` ````
+
+            - item with `code`
+              ```rust,ignore
+              let s = "```";
+              ```
+            1. numbered
+               > quoted #text
+
+                indented code
+                with `backticks`
+
+            | a | b |
+            |---|---|
+            | `x|y` | 1 + 2 = 3 |
+
+            ~~struck~~ and a note[^n-1].
+
+            [^n-1]: The *note* #body.
+            "#}
+        .to_string()];
+        for entry in walkdir::WalkDir::new("samples/test_book/src") {
+            let entry = entry.unwrap();
+            if entry.path().extension().is_some_and(|extension| extension == "md") {
+                chapters.push(std::fs::read_to_string(entry.path()).unwrap());
+            }
+        }
+        let converter = Converter::builder().base_dir("samples/test_book/src").build();
+        for markdown in chapters {
+            let typst = converter.convert(&markdown).typst;
+            let mut found = Vec::new();
+            unintended_nodes(&typst_syntax::parse(&typst), &mut found);
+            assert!(found.is_empty(), "{:?} in\n{}", found, typst);
+        }
+    }
+
+    // the text of every list item and content block, `None` for one that has more than text
+    fn item_texts(node: &typst_syntax::SyntaxNode, texts: &mut Vec<Option<String>>) {
+        use typst_syntax::SyntaxKind;
+        match node.kind() {
+            SyntaxKind::ListItem | SyntaxKind::EnumItem | SyntaxKind::ContentBlock => {
+                for child in node.children().filter(|child| child.kind() == SyntaxKind::Markup) {
+                    let mut text = String::new();
+                    texts.push(plain_text(child, &mut text).then(|| text.trim().to_string()));
+                }
+            }
+            _ => node.children().for_each(|child| item_texts(child, texts)),
+        }
+    }
+
+    #[test]
+    fn test_markers_in_items() {
+        let markdown = "- = x\n- \\- y\n\n3. \\+ z\n\n| = a | - b |\n|---|---|\n| + c | 2. d |\n";
+        let typst = Converter::builder().build().convert(markdown).typst;
+        let mut texts = Vec::new();
+        item_texts(&typst_syntax::parse(&typst), &mut texts);
+        let expected = ["= x", "- y", "+ z", "= a", "- b", "+ c", "2. d"].map(|text| Some(text.to_string()));
+        assert_eq!(texts, expected, "in\n{}", typst);
+    }
+}
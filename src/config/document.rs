@@ -0,0 +1,105 @@
+use super::{
+    parameters::typst_string,
+    Config,
+};
+
+/// PDF document properties, written as `#set document(..)` and `#set text(lang: ..)`
+/// ahead of the template so every PDF has them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub keywords: Vec<String>,
+    pub date: Option<chrono::NaiveDate>,
+    pub language: Option<String>,
+}
+
+// a string, or an array of strings, or a comma separated string when `split` is set
+fn string_list(value: &toml::Value, split: bool) -> Vec<String> {
+    match value {
+        toml::Value::String(s) if split => s.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        toml::Value::String(s) => vec![s.clone()],
+        toml::Value::Array(items) => items.iter().filter_map(|item| item.as_str()).map(|s| s.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl DocumentMetadata {
+    pub fn to_typst(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(title) = &self.title {
+            fields.push(format!("title: {}", typst_string(title)));
+        }
+        if !self.authors.is_empty() {
+            let authors: Vec<String> = self.authors.iter().map(|author| typst_string(author)).collect();
+            fields.push(format!("author: ({},)", authors.join(", ")));
+        }
+        if !self.keywords.is_empty() {
+            let keywords: Vec<String> = self.keywords.iter().map(|keyword| typst_string(keyword)).collect();
+            fields.push(format!("keywords: ({},)", keywords.join(", ")));
+        }
+        if let Some(date) = &self.date {
+            use chrono::Datelike;
+            fields.push(format!("date: datetime(year: {}, month: {}, day: {})", date.year(), date.month(), date.day()));
+        }
+
+        let mut result = String::new();
+        if !fields.is_empty() {
+            result.push_str(&format!("#set document({})\n", fields.join(", ")));
+        }
+        // typst wants the ISO 639 language and the ISO 3166 region apart, `en-US` -> `en` and `US`
+        if let Some(language) = &self.language {
+            let mut parts = language.split(['-', '_']);
+            let lang = parts.next().unwrap_or_default().to_lowercase();
+            match parts.next() {
+                Some(region) if region.len() == 2 => {
+                    result.push_str(&format!("#set text(lang: {}, region: {})\n", typst_string(&lang), typst_string(&region.to_uppercase())))
+                }
+                _ => result.push_str(&format!("#set text(lang: {})\n", typst_string(&lang))),
+            }
+        }
+        result
+    }
+}
+
+impl Config {
+    /// Document properties from `[book]`, overridden by the `doc_title`, `doc_author`,
+    /// `keywords` and `book_language` template parameters
+    pub fn get_document_metadata(&self, ctx: &mdbook::renderer::RenderContext) -> DocumentMetadata {
+        let book = &ctx.config.book;
+        let parameters = self.get_template_parameters(ctx);
+        let parameter = |name: &str| parameters.get(name);
+        DocumentMetadata {
+            title: parameter("doc_title").and_then(|title| title.as_str()).map(|title| title.to_string()).or_else(|| book.title.clone()),
+            authors: match parameter("doc_author") {
+                Some(author) => string_list(author, false),
+                None => book.authors.clone(),
+            },
+            keywords: parameter("keywords").map(|keywords| string_list(keywords, true)).unwrap_or_default(),
+            date: Some(self.build_date()),
+            language: parameter("book_language").and_then(|language| language.as_str()).map(|language| language.to_string()).or_else(|| book.language.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_metadata() {
+        let metadata = DocumentMetadata {
+            title: Some("A \"quoted\" guide".to_string()),
+            authors: vec!["Ann".to_string()],
+            keywords: string_list(&toml::Value::from("k8s, helm,"), true),
+            date: chrono::NaiveDate::from_ymd_opt(2025, 2, 26),
+            language: Some("en-us".to_string()),
+        };
+        assert_eq!(
+            metadata.to_typst(),
+            "#set document(title: \"A \\\"quoted\\\" guide\", author: (\"Ann\",), keywords: (\"k8s\", \"helm\",), \
+             date: datetime(year: 2025, month: 2, day: 26))\n#set text(lang: \"en\", region: \"US\")\n"
+        );
+        assert_eq!(DocumentMetadata::default().to_typst(), "");
+    }
+}
@@ -0,0 +1,507 @@
+use indexmap::IndexMap;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use super::{
+    converter::{HeadingAttributes, LinkTarget},
+    escape::{escape_markup, raw_block, raw_inline},
+    parameters::typst_string,
+};
+
+/// The markdown elements an [`ElementHandler`] or an `element_functions` entry renders
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+    /// `#name(depth: 2)[Title]`
+    Heading,
+    /// `#name[body]`
+    Quote,
+    /// `#name("code", lang: "rust")`
+    CodeBlock,
+    /// `#name("code")`
+    Code,
+    /// `#name("path", width: 80%, alt: "text")`
+    Image,
+    /// `#name("url")[text]` or `#name(<label>)[text]`
+    Link,
+    /// `#name(columns: 2, table.header(..), ..)`
+    Table,
+    /// `#name[text]`
+    Emphasis,
+    /// `#name[text]`
+    Strong,
+    /// `#name[text]`
+    Strikethrough,
+    /// `#name[note]`
+    Footnote,
+    /// `#name()`
+    Rule,
+    /// `#name(true)` for a checked task list item
+    Task,
+}
+
+/// `deserialize_with` for `element_functions`, every function has to be a Typst identifier
+/// or a path of them like `box.quote`, the key of one that isn't is in the error
+pub fn deserialize_element_functions<'de, D: Deserializer<'de>>(deserializer: D) -> Result<IndexMap<Element, String>, D::Error> {
+    let functions = IndexMap::<Element, String>::deserialize(deserializer)?;
+    for (element, function) in &functions {
+        if !function.split('.').all(typst_syntax::is_ident) {
+            let key = toml::Value::try_from(element).ok().and_then(|key| key.as_str().map(str::to_string)).unwrap_or_default();
+            return Err(de::Error::custom(format!("element_functions.{}: {:?} is not a Typst function name", key, function)));
+        }
+    }
+    Ok(functions)
+}
+
+/// A code block, its text is only known at its end
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CodeBlock {
+    /// the fenced code block language
+    pub language: Option<String>,
+    pub text: String,
+    /// `false` for an indented code block
+    pub fenced: bool,
+    /// the indentation of the list the code block is in
+    pub indent: String,
+}
+
+/// A resolved image with its alt text
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageElement {
+    pub path: String,
+    pub width: Option<String>,
+    pub alt: String,
+}
+
+/// Writes the Typst markup of the markdown elements.
+///
+/// Every method has the default rendering, a handler only implements the ones it changes.
+/// Elements with a body have a start and an end method, the body is written in between.
+pub trait ElementHandler: Send + Sync {
+    fn heading_start(&self, heading: &HeadingAttributes, out: &mut String) {
+        heading.push_start(out);
+    }
+
+    fn heading_end(&self, heading: &HeadingAttributes, out: &mut String) {
+        heading.push_end(out);
+    }
+
+    fn quote_start(&self, out: &mut String) {
+        out.push_str("#quote[");
+    }
+
+    fn quote_end(&self, out: &mut String) {
+        out.push(']');
+    }
+
+    fn code_block(&self, code: &CodeBlock, out: &mut String) {
+        out.push_str(&raw_block(&code.text, code.language.as_deref(), &code.indent));
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        out.push_str(&raw_inline(code));
+    }
+
+    fn image(&self, image: &ImageElement, out: &mut String) {
+        out.push_str(&format!("#figure(\n  image({}{}{}),\n  caption: none)", typst_string(&image.path), width_argument(image), alt_argument(image)));
+    }
+
+    fn link_start(&self, target: &LinkTarget, out: &mut String) {
+        out.push_str(&format!("#link({})[", link_argument(target)));
+    }
+
+    fn link_end(&self, out: &mut String) {
+        out.push(']');
+    }
+
+    fn table_start(&self, columns: usize, out: &mut String) {
+        out.push_str(&format!("#table(\n  columns: {},\n", columns));
+    }
+
+    fn table_end(&self, out: &mut String) {
+        out.push_str(")\n");
+    }
+
+    fn table_header_start(&self, out: &mut String) {
+        out.push_str("  table.header(");
+    }
+
+    fn table_header_end(&self, out: &mut String) {
+        out.push_str("),\n");
+    }
+
+    /// the cells are separated by the converter
+    fn table_cell_start(&self, out: &mut String) {
+        out.push('[');
+    }
+
+    fn table_cell_end(&self, out: &mut String) {
+        out.push(']');
+    }
+
+    /// `number` is `None` in a bullet list, the item's number in an ordered list.
+    /// the indentation of nested lists is written by the converter.
+    fn list_item_start(&self, number: Option<u64>, out: &mut String) {
+        match number {
+            None => out.push_str("- "),
+            // the items of a list starting at 1 are numbered by typst
+            Some(1) => out.push_str("+ "),
+            Some(number) => out.push_str(&format!("{}. ", number)),
+        }
+    }
+
+    fn list_item_end(&self, _out: &mut String) {}
+
+    /// the line breaks around paragraphs are written by the converter
+    fn paragraph_start(&self, _out: &mut String) {}
+
+    fn paragraph_end(&self, _out: &mut String) {}
+
+    fn emphasis_start(&self, out: &mut String) {
+        out.push_str(" _");
+    }
+
+    fn emphasis_end(&self, out: &mut String) {
+        out.push_str("_ ");
+    }
+
+    fn strong_start(&self, out: &mut String) {
+        out.push_str(" *");
+    }
+
+    fn strong_end(&self, out: &mut String) {
+        out.push_str("* ");
+    }
+
+    fn strikethrough_start(&self, out: &mut String) {
+        out.push_str("#strike[");
+    }
+
+    fn strikethrough_end(&self, out: &mut String) {
+        out.push(']');
+    }
+
+    fn footnote_start(&self, out: &mut String) {
+        out.push_str("#footnote[");
+    }
+
+    fn footnote_end(&self, out: &mut String) {
+        out.push(']');
+    }
+
+    fn footnote_reference(&self, name: &str, out: &mut String) {
+        out.push_str(&format!("#footnote[See note {}]", escape_markup(name, "See note ")));
+    }
+
+    fn rule(&self, out: &mut String) {
+        out.push_str("\n#line(length: 100%)\n");
+    }
+
+    fn task(&self, checked: bool, out: &mut String) {
+        out.push_str(if checked { "[x] " } else { "[ ] " });
+    }
+}
+
+/// The built-in rendering
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultElements;
+
+impl ElementHandler for DefaultElements {}
+
+/// Calls the user named Typst functions of `element_functions`, and the wrapped handler for the other elements
+pub(crate) struct FunctionElements<'a> {
+    pub functions: IndexMap<Element, String>,
+    pub handler: Box<dyn ElementHandler + 'a>,
+}
+
+impl FunctionElements<'_> {
+    fn function(&self, element: Element) -> Option<&str> {
+        self.functions.get(&element).map(String::as_str)
+    }
+}
+
+impl ElementHandler for FunctionElements<'_> {
+    fn heading_start(&self, heading: &HeadingAttributes, out: &mut String) {
+        match self.function(Element::Heading) {
+            Some(function) => heading.push_function_start(function, out),
+            None => self.handler.heading_start(heading, out),
+        }
+    }
+
+    fn heading_end(&self, heading: &HeadingAttributes, out: &mut String) {
+        match self.function(Element::Heading) {
+            Some(_) => heading.push_function_end(out),
+            None => self.handler.heading_end(heading, out),
+        }
+    }
+
+    fn quote_start(&self, out: &mut String) {
+        match self.function(Element::Quote) {
+            Some(function) => out.push_str(&format!("#{}[", function)),
+            None => self.handler.quote_start(out),
+        }
+    }
+
+    fn quote_end(&self, out: &mut String) {
+        match self.function(Element::Quote) {
+            Some(_) => out.push(']'),
+            None => self.handler.quote_end(out),
+        }
+    }
+
+    fn code_block(&self, code: &CodeBlock, out: &mut String) {
+        match self.function(Element::CodeBlock) {
+            Some(function) => {
+                out.push_str(&format!("{}#{}({}", code.indent, function, typst_string(&code.text)));
+                if let Some(language) = &code.language {
+                    out.push_str(&format!(", lang: {}", typst_string(language)));
+                }
+                out.push(')');
+            }
+            None => self.handler.code_block(code, out),
+        }
+    }
+
+    fn code(&self, code: &str, out: &mut String) {
+        match self.function(Element::Code) {
+            Some(function) => out.push_str(&format!("#{}({})", function, typst_string(code))),
+            None => self.handler.code(code, out),
+        }
+    }
+
+    fn image(&self, image: &ImageElement, out: &mut String) {
+        match self.function(Element::Image) {
+            Some(function) => out.push_str(&format!("#{}({}{}{})", function, typst_string(&image.path), width_argument(image), alt_argument(image))),
+            None => self.handler.image(image, out),
+        }
+    }
+
+    fn link_start(&self, target: &LinkTarget, out: &mut String) {
+        match self.function(Element::Link) {
+            Some(function) => out.push_str(&format!("#{}({})[", function, link_argument(target))),
+            None => self.handler.link_start(target, out),
+        }
+    }
+
+    fn link_end(&self, out: &mut String) {
+        match self.function(Element::Link) {
+            Some(_) => out.push(']'),
+            None => self.handler.link_end(out),
+        }
+    }
+
+    fn table_start(&self, columns: usize, out: &mut String) {
+        match self.function(Element::Table) {
+            Some(function) => out.push_str(&format!("#{}(\n  columns: {},\n", function, columns)),
+            None => self.handler.table_start(columns, out),
+        }
+    }
+
+    fn table_end(&self, out: &mut String) {
+        match self.function(Element::Table) {
+            Some(_) => out.push_str(")\n"),
+            None => self.handler.table_end(out),
+        }
+    }
+
+    fn table_header_start(&self, out: &mut String) {
+        self.handler.table_header_start(out);
+    }
+
+    fn table_header_end(&self, out: &mut String) {
+        self.handler.table_header_end(out);
+    }
+
+    fn table_cell_start(&self, out: &mut String) {
+        self.handler.table_cell_start(out);
+    }
+
+    fn table_cell_end(&self, out: &mut String) {
+        self.handler.table_cell_end(out);
+    }
+
+    fn list_item_start(&self, number: Option<u64>, out: &mut String) {
+        self.handler.list_item_start(number, out);
+    }
+
+    fn list_item_end(&self, out: &mut String) {
+        self.handler.list_item_end(out);
+    }
+
+    fn paragraph_start(&self, out: &mut String) {
+        self.handler.paragraph_start(out);
+    }
+
+    fn paragraph_end(&self, out: &mut String) {
+        self.handler.paragraph_end(out);
+    }
+
+    fn emphasis_start(&self, out: &mut String) {
+        match self.function(Element::Emphasis) {
+            Some(function) => out.push_str(&format!("#{}[", function)),
+            None => self.handler.emphasis_start(out),
+        }
+    }
+
+    fn emphasis_end(&self, out: &mut String) {
+        match self.function(Element::Emphasis) {
+            Some(_) => out.push(']'),
+            None => self.handler.emphasis_end(out),
+        }
+    }
+
+    fn strong_start(&self, out: &mut String) {
+        match self.function(Element::Strong) {
+            Some(function) => out.push_str(&format!("#{}[", function)),
+            None => self.handler.strong_start(out),
+        }
+    }
+
+    fn strong_end(&self, out: &mut String) {
+        match self.function(Element::Strong) {
+            Some(_) => out.push(']'),
+            None => self.handler.strong_end(out),
+        }
+    }
+
+    fn strikethrough_start(&self, out: &mut String) {
+        match self.function(Element::Strikethrough) {
+            Some(function) => out.push_str(&format!("#{}[", function)),
+            None => self.handler.strikethrough_start(out),
+        }
+    }
+
+    fn strikethrough_end(&self, out: &mut String) {
+        match self.function(Element::Strikethrough) {
+            Some(_) => out.push(']'),
+            None => self.handler.strikethrough_end(out),
+        }
+    }
+
+    fn footnote_start(&self, out: &mut String) {
+        match self.function(Element::Footnote) {
+            Some(function) => out.push_str(&format!("#{}[", function)),
+            None => self.handler.footnote_start(out),
+        }
+    }
+
+    fn footnote_end(&self, out: &mut String) {
+        match self.function(Element::Footnote) {
+            Some(_) => out.push(']'),
+            None => self.handler.footnote_end(out),
+        }
+    }
+
+    fn footnote_reference(&self, name: &str, out: &mut String) {
+        match self.function(Element::Footnote) {
+            Some(function) => out.push_str(&format!("#{}[See note {}]", function, escape_markup(name, "See note "))),
+            None => self.handler.footnote_reference(name, out),
+        }
+    }
+
+    fn rule(&self, out: &mut String) {
+        match self.function(Element::Rule) {
+            Some(function) => out.push_str(&format!("\n#{}()\n", function)),
+            None => self.handler.rule(out),
+        }
+    }
+
+    fn task(&self, checked: bool, out: &mut String) {
+        match self.function(Element::Task) {
+            Some(function) => out.push_str(&format!("#{}({}) ", function, checked)),
+            None => self.handler.task(checked, out),
+        }
+    }
+}
+
+fn width_argument(image: &ImageElement) -> String {
+    match &image.width {
+        Some(width) => format!(", width: {}", width),
+        None => String::new(),
+    }
+}
+
+fn alt_argument(image: &ImageElement) -> String {
+    let alt = image.alt.trim();
+    if alt.is_empty() {
+        String::new()
+    } else {
+        format!(", alt: {}", typst_string(alt))
+    }
+}
+
+fn link_argument(target: &LinkTarget) -> String {
+    match target {
+        LinkTarget::Label(label) => format!("<{}>", label),
+        LinkTarget::Url(url) => typst_string(url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::converter::Converter;
+
+    #[test]
+    fn test_element_handlers() {
+        struct Boxed;
+        impl ElementHandler for Boxed {
+            fn quote_start(&self, out: &mut String) {
+                out.push_str("#block(stroke: 1pt)[");
+            }
+        }
+        let markdown = "> quoted\n\n---\n\n- [x] done\n\n```rust\nfn main() {}\n```\n";
+
+        let typst = Converter::builder().element_handler(Boxed).build().convert(markdown).typst;
+        assert!(typst.contains("#block(stroke: 1pt)["));
+        assert!(typst.contains("#line(length: 100%)"));
+
+        let functions: IndexMap<Element, String> = toml::from_str("quote = \"mybox\"\nrule = \"divider\"\ntask = \"task\"\ncode_block = \"listing\"").unwrap();
+        let typst = Converter::builder().element_handler(Boxed).element_functions(functions.clone()).build().convert(markdown).typst;
+        assert!(typst.contains("#mybox[\nquoted"));
+        assert!(typst.contains("#divider()"));
+        assert!(typst.contains("#task(true) done"));
+        assert!(typst.contains("#listing(\"fn main() {}\\n\", lang: \"rust\")"));
+        assert!(toml::from_str::<IndexMap<Element, String>>("paragraph = \"p\"").is_err());
+
+        // the other markup comes from the handler too
+        struct Cells;
+        impl ElementHandler for Cells {
+            fn table_header_start(&self, out: &mut String) {
+                out.push_str("  table.header(repeat: true, ");
+            }
+            fn table_cell_start(&self, out: &mut String) {
+                out.push_str("table.cell(inset: 2pt)[");
+            }
+            fn list_item_start(&self, _number: Option<u64>, out: &mut String) {
+                out.push_str("/ Item: ");
+            }
+            fn paragraph_start(&self, out: &mut String) {
+                out.push_str("#par[");
+            }
+            fn paragraph_end(&self, out: &mut String) {
+                out.push(']');
+            }
+        }
+        let typst = Converter::builder().element_handler(Cells).element_functions(functions).build().convert("Text\n\n- one\n\n| a |\n|---|\n| 1 |\n").typst;
+        assert!(typst.contains("#par[Text]"));
+        assert!(typst.contains("/ Item: one"));
+        assert!(typst.contains("table.header(repeat: true, table.cell(inset: 2pt)[a]"));
+        assert!(typst.contains("table.cell(inset: 2pt)[1]"));
+    }
+
+    #[test]
+    fn test_element_function_names() {
+        #[derive(Debug, Deserialize)]
+        struct Functions {
+            #[serde(deserialize_with = "deserialize_element_functions")]
+            element_functions: IndexMap<Element, String>,
+        }
+        let functions: Functions = toml::from_str("[element_functions]\nquote = \"mybox\"\ncode_block = \"listings.code\"").unwrap();
+        assert_eq!(functions.element_functions[&Element::CodeBlock], "listings.code");
+
+        let error = toml::from_str::<Functions>("[element_functions]\ncode_block = \"my box\"").unwrap_err().to_string();
+        assert!(error.contains("element_functions.code_block: \"my box\" is not a Typst function name"), "{}", error);
+        assert!(toml::from_str::<Functions>("[element_functions]\nquote = \"quote[x] //\"").is_err());
+        assert!(toml::from_str::<Functions>("[element_functions]\nquote = \"\"").is_err());
+    }
+}
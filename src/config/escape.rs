@@ -0,0 +1,144 @@
+use super::parameters::typst_string;
+
+/// Escape text for Typst markup, so it's all plain text.
+///
+/// `before` is the markup the text is appended to: the markers that only count at the start of a line,
+/// a list item or a content block (`=`, `-`, `+`, `1.`) are escaped there,
+/// and so is a `(` or `.` that would continue a function call.
+pub fn escape_markup(text: &str, before: &str) -> String {
+    let mut result = String::with_capacity(text.len() + text.len() / 4);
+    let mut line_start = at_markup_start(before);
+    let after_call = matches!(before.chars().next_back(), Some(')' | ']'));
+    // markdown splits `1\. x` into `1` and `. x`, the `.` makes the number before an enum marker
+    let number = before.trim_end_matches(|c: char| c.is_ascii_digit());
+    let after_number = number.len() < before.len() && at_markup_start(number);
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        // typst also breaks lines at form feeds, vertical tabs and the unicode line separators,
+        // markdown doesn't, they're written as unicode escapes
+        if c != '\n' && typst_syntax::is_newline(c) {
+            result.push_str(&format!("\\u{{{:x}}}", c as u32));
+            continue;
+        }
+        let escape = match c {
+            // `//` and `/*` start comments, so does a `/` next to the `*` of strong markup
+            '\\' | '#' | '*' | '_' | '`' | '$' | '[' | ']' | '<' | '>' | '@' | '~' | '/' => true,
+            // headings and list items
+            '=' | '-' | '+' => line_start,
+            // `1.` starts an enum item
+            '0'..='9' => line_start && chars.clone().find(|c| !c.is_ascii_digit()) == Some('.'),
+            // `#link("..")[..](..)` or `.field` would be part of the call before
+            '(' => after_call && result.is_empty(),
+            '.' => (after_call || after_number) && result.is_empty(),
+            _ => false,
+        };
+        if escape {
+            result.push('\\');
+        }
+        result.push(c);
+        if c == '\n' {
+            line_start = true;
+        } else if !c.is_whitespace() {
+            line_start = false;
+        }
+    }
+    result
+}
+
+// whether markup appended to `before` starts a line, the body of a list or enum item, or a content block
+fn at_markup_start(before: &str) -> bool {
+    let line = before.rsplit(typst_syntax::is_newline).next().unwrap_or_default().trim();
+    if line.is_empty() || line == "-" || line == "+" {
+        return true;
+    }
+    if let Some(number) = line.strip_suffix('.') {
+        return !number.is_empty() && number.chars().all(|c| c.is_ascii_digit());
+    }
+    // an opening `[`, not an escaped one
+    match line.strip_suffix('[') {
+        Some(rest) => rest.chars().rev().take_while(|c| *c == '\\').count() % 2 == 0,
+        None => false,
+    }
+}
+
+/// Inline raw text, `` `code` `` or `#raw("code")` when the code has a backtick
+pub fn raw_inline(code: &str) -> String {
+    if code.is_empty() || code.contains('`') {
+        format!("#raw({})", typst_string(code))
+    } else {
+        format!("`{}`", code)
+    }
+}
+
+/// A raw block, fenced with more backticks than any run in the code.
+/// A language that isn't a plain name, like `c++`, goes through `#raw(..)`.
+pub fn raw_block(code: &str, language: Option<&str>, indent: &str) -> String {
+    let language = language.unwrap_or_default();
+    if !language.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') || language.starts_with('-') {
+        return format!("{}#raw({}, block: true, lang: {})", indent, typst_string(code), typst_string(language));
+    }
+    let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or_default();
+    let fence = "`".repeat((longest + 1).max(3));
+    format!("{}{}{}\n{}\n{}", indent, fence, language, code, fence)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use typst_syntax::{SyntaxKind, SyntaxNode};
+
+    // the text a markup tree shows, `false` when it has anything but text
+    pub(crate) fn plain_text(node: &SyntaxNode, text: &mut String) -> bool {
+        match node.kind() {
+            SyntaxKind::Markup => node.children().all(|child| plain_text(child, text)),
+            // quotes and shorthands like `--` and `-1` are only typography
+            SyntaxKind::Text | SyntaxKind::Space | SyntaxKind::SmartQuote | SyntaxKind::Shorthand => {
+                text.push_str(node.text());
+                true
+            }
+            SyntaxKind::Escape => {
+                text.push_str(&node.text()[1..]);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_escape_markup() {
+        let samples = [
+            "#hashtag and unquoted * star",
+            "= not a heading",
+            "- not a list",
+            "+ not an enum",
+            "12. not an enum either",
+            "/ not: a term",
+            "a // not a comment /* nor this */",
+            "<not-a-label> @not-a-ref ~ \\ $x$ `y` [z] _w_",
+            "say \"hi\" it's 1.5 - 2 = -0.5",
+            "https://example.com/a_b",
+        ];
+        for sample in samples {
+            let markup = escape_markup(sample, "\n");
+            let mut text = String::new();
+            assert!(plain_text(&typst_syntax::parse(&markup), &mut text), "{} parsed as more than text", markup);
+            assert_eq!(text, sample);
+        }
+        // in the middle of a line only what's special everywhere is escaped
+        assert_eq!(escape_markup("- 1. x", "a"), "- 1. x");
+        // list items and content blocks start like a line
+        for before in ["\n  - ", "\n+ ", "\n12. ", "  table.header([", "[a], ["] {
+            assert_eq!(escape_markup("= x", before), "\\= x", "after {:?}", before);
+        }
+        assert_eq!(escape_markup("= x", "a \\["), "= x");
+        assert_eq!(escape_markup(". x", "\n  - 12"), "\\. x");
+        assert_eq!(escape_markup(". x", "a 12"), ". x");
+        assert_eq!(escape_markup("a\u{c}= x\u{2028}", "\n"), "a\\u{c}= x\\u{2028}");
+        assert_eq!(escape_markup("= x", "a\u{2028}"), "\\= x");
+        assert_eq!(escape_markup(".field (args)", "#link(\"x\")[x]"), "\\.field (args)");
+
+        assert_eq!(raw_inline("a`b"), "#raw(\"a`b\")");
+        assert_eq!(raw_block("```\nx\n```\n", Some("md"), "  "), "  ````md\n```\nx\n```\n\n````");
+        assert_eq!(raw_block("int x;", Some("c++"), ""), "#raw(\"int x;\", block: true, lang: \"c++\")");
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+/// Decide which chapters go into a PDF.
+///
+/// `include`/`exclude` are glob patterns over the chapter source path (relative to the `src` folder),
+/// `audiences` keeps only chapters tagged for one of the listed audiences.
+/// Chapters without tags are always kept by the audience check.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChapterFilter {
+    /// only chapters matching one of these patterns are included, all chapters if empty
+    pub include: Vec<String>,
+
+    /// chapters matching one of these patterns are left out
+    pub exclude: Vec<String>,
+
+    /// only untagged chapters and chapters tagged with one of these audiences are included
+    pub audiences: Vec<String>,
+}
+
+impl ChapterFilter {
+    /// Empty fields fall back to the values of `fallback`
+    pub fn or(&self, fallback: &ChapterFilter) -> ChapterFilter {
+        let pick = |own: &Vec<String>, other: &Vec<String>| if own.is_empty() { other.clone() } else { own.clone() };
+        ChapterFilter {
+            include: pick(&self.include, &fallback.include),
+            exclude: pick(&self.exclude, &fallback.exclude),
+            audiences: pick(&self.audiences, &fallback.audiences),
+        }
+    }
+
+    pub fn compile(&self) -> anyhow::Result<CompiledChapterFilter> {
+        Ok(CompiledChapterFilter {
+            include: if self.include.is_empty() { None } else { Some(build_glob_set(&self.include)?) },
+            exclude: build_glob_set(&self.exclude)?,
+            audiences: self.audiences.clone(),
+        })
+    }
+}
+
+pub struct CompiledChapterFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    audiences: Vec<String>,
+}
+
+impl CompiledChapterFilter {
+    pub fn matches(&self, source_path: &Path, tags: &[String]) -> bool {
+        // mdBook source paths use the platform separator, the patterns always use `/`
+        let source_path = source_path.to_string_lossy().replace('\\', "/");
+        if let Some(include) = &self.include {
+            if !include.is_match(&source_path) {
+                return false;
+            }
+        }
+        if self.exclude.is_match(&source_path) {
+            return false;
+        }
+        tags.is_empty() || self.audiences.is_empty() || tags.iter().any(|tag| self.audiences.contains(tag))
+    }
+}
+
+pub(super) fn build_glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| anyhow::anyhow!("invalid pattern {}: {}", pattern, e))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+/// Audience tags given in SUMMARY.md as trailing `@tag` words of the chapter name,
+/// e.g. `- [Deployment notes @internal](internal/deploy.md)`
+pub fn summary_tags(chapter_name: &str) -> Vec<String> {
+    chapter_name
+        .split_whitespace()
+        .rev()
+        .map_while(|word| word.strip_prefix('@'))
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chapter_filter() {
+        let filter = ChapterFilter {
+            exclude: vec!["internal/**".to_string()],
+            audiences: vec!["customer".to_string()],
+            ..Default::default()
+        }
+        .compile()
+        .unwrap();
+        assert!(filter.matches(Path::new("guide/setup.md"), &[]));
+        assert!(!filter.matches(Path::new("internal/ops/runbook.md"), &[]));
+        assert!(!filter.matches(Path::new("guide/notes.md"), &["internal".to_string()]));
+        assert!(filter.matches(Path::new("guide/faq.md"), &["customer".to_string(), "internal".to_string()]));
+
+        let filter = ChapterFilter { include: vec!["guide/*.md".to_string()], ..Default::default() }.compile().unwrap();
+        assert!(filter.matches(Path::new("guide/setup.md"), &[]));
+        assert!(!filter.matches(Path::new("README.md"), &[]));
+
+        assert_eq!(summary_tags("Deployment notes @internal @ops"), vec!["ops", "internal"]);
+        assert!(summary_tags("Contact: me@example.com").is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use pulldown_cmark::{Event, Parser};
+
+use super::{front_matter::FrontMatter, Config};
+
+// Typst's embedded fonts (Libertinus Serif, New Computer Modern, DejaVu Sans Mono)
+// cover Latin, Greek, Cyrillic and the common punctuation
+fn covered_by_embedded_fonts(c: char) -> bool {
+    matches!(c as u32, 0x0000..=0x052F | 0x1E00..=0x1FFF | 0x2000..=0x206F | 0x20A0..=0x20CF | 0x2100..=0x218F)
+}
+
+/// The characters of a chapter that need a glyph, code included
+pub fn chapter_chars(content: &str) -> BTreeSet<char> {
+    let mut chars = BTreeSet::new();
+    for event in Parser::new(FrontMatter::strip(content)) {
+        if let Event::Text(text) | Event::Code(text) = event {
+            chars.extend(text.chars().filter(|c| !c.is_whitespace() && !c.is_control()));
+        }
+    }
+    chars
+}
+
+/// The font names in a PDF, without the subset prefix (`ABCDEF+`)
+pub fn pdf_fonts(pdf: &[u8]) -> BTreeSet<String> {
+    let pattern = regex::bytes::Regex::new(r"/BaseFont\s*/([A-Za-z0-9+\-_.#]+)").unwrap();
+    pattern
+        .captures_iter(pdf)
+        .map(|captures| {
+            let name = String::from_utf8_lossy(&captures[1]).to_string();
+            match name.split_once('+') {
+                Some((prefix, font)) if prefix.len() == 6 => font.to_string(),
+                _ => name,
+            }
+        })
+        .collect()
+}
+
+impl Config {
+    /// `font_paths`, relative to the book root or else to `template_dir`
+    pub fn get_font_paths(&self, ctx: &mdbook::renderer::RenderContext) -> Vec<PathBuf> {
+        let mut result = Vec::new();
+        for font_path in &self.font_paths {
+            let path = Path::new(font_path);
+            let candidates = if path.is_absolute() {
+                vec![path.to_path_buf()]
+            } else {
+                vec![ctx.root.join(path), self.get_template_dir(ctx).join(path)]
+            };
+            match candidates.into_iter().find(|candidate| candidate.exists()) {
+                Some(path) => result.push(path),
+                None => log::warn!("font path {} not found", font_path),
+            }
+        }
+        result
+    }
+
+    fn load_fonts(&self, ctx: &mdbook::renderer::RenderContext) -> fontdb::Database {
+        let mut fonts = fontdb::Database::new();
+        if !self.ignore_system_fonts {
+            fonts.load_system_fonts();
+        }
+        for path in self.get_font_paths(ctx) {
+            if path.is_dir() {
+                fonts.load_fonts_dir(&path);
+            } else if let Err(e) = fonts.load_font_file(&path) {
+                log::warn!("failed to load font {}: {}", path.display(), e);
+            }
+        }
+        fonts
+    }
+
+    /// Report the fonts embedded in a generated PDF
+    pub fn report_pdf_fonts(&self, pdf_file: &Path) -> anyhow::Result<()> {
+        let fonts = pdf_fonts(&std::fs::read(pdf_file)?);
+        let fonts: Vec<String> = fonts.into_iter().collect();
+        log::info!("fonts used in {}: {}", pdf_file.display(), fonts.join(", "));
+        Ok(())
+    }
+
+    /// Report, per chapter, the characters none of the available fonts has a glyph for.
+    /// They show up as empty boxes in the PDF.
+    pub fn report_missing_glyphs(&self, ctx: &mdbook::renderer::RenderContext) {
+        let mut chapters: BTreeMap<String, BTreeSet<char>> = BTreeMap::new();
+        for item in ctx.book.iter() {
+            if let mdbook::book::BookItem::Chapter(chapter) = item {
+                let front_matter = FrontMatter::extract(&chapter.content).0.unwrap_or_default();
+                if chapter.path.is_none() || front_matter.pdf.skip {
+                    continue;
+                }
+                let chars: BTreeSet<char> =
+                    chapter_chars(&chapter.content).into_iter().filter(|c| !covered_by_embedded_fonts(*c)).collect();
+                if !chars.is_empty() {
+                    chapters.insert(chapter.name.clone(), chars);
+                }
+            }
+        }
+        let mut missing: BTreeSet<char> = chapters.values().flatten().copied().collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        // parse every font once and drop the characters it covers
+        let fonts = self.load_fonts(ctx);
+        for face in fonts.faces() {
+            if missing.is_empty() {
+                break;
+            }
+            fonts.with_face_data(face.id, |data, index| {
+                if let Ok(font) = ttf_parser::Face::parse(data, index) {
+                    missing.retain(|c| font.glyph_index(*c).is_none());
+                }
+            });
+        }
+
+        for (chapter, chars) in &chapters {
+            let chars: String = chars.iter().filter(|c| missing.contains(c)).collect();
+            if !chars.is_empty() {
+                log::warn!("chapter {}: no font has a glyph for {}, add a font with font_paths", chapter, chars);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_font_report() {
+        let pdf = b"<< /Type /Font /BaseFont /ABCDEF+LibertinusSerif-Regular >>\n<</BaseFont/DejaVuSansMono>>";
+        assert_eq!(pdf_fonts(pdf).into_iter().collect::<Vec<_>>(), vec!["DejaVuSansMono", "LibertinusSerif-Regular"]);
+
+        let chars = chapter_chars("---\ntags: [x]\n---\n# 标题\n\nA `b` ✓");
+        assert_eq!(chars.into_iter().collect::<String>(), "Ab✓标题");
+        assert!(covered_by_embedded_fonts('é'));
+        assert!(!covered_by_embedded_fonts('标'));
+    }
+}
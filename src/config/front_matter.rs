@@ -0,0 +1,162 @@
+use pulldown_cmark::{Event, MetadataBlockKind, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+
+/// Per-chapter options read from a YAML (`---`) or TOML (`+++`) front matter block
+/// at the very beginning of a chapter, for example:
+///
+/// ```yaml
+/// ---
+/// pdf:
+///   page_break: true
+///   landscape: true
+///   max_width: 0.8
+/// tags: [internal]
+/// metadata:
+///   reviewed_by: QA team
+/// ---
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrontMatter {
+    /// options that only affect the PDF output
+    pub pdf: ChapterPdfOptions,
+
+    /// audience tags, used by the `audiences` chapter filter
+    pub tags: Vec<String>,
+
+    /// custom metadata, exposed to the template as `#metadata(..) <mdbook-chapter-metadata>`
+    pub metadata: toml::Table,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChapterPdfOptions {
+    /// leave this chapter out of the PDF
+    pub skip: bool,
+
+    /// force a page break before this chapter
+    pub page_break: bool,
+
+    /// render the pages of this chapter in landscape orientation
+    pub landscape: bool,
+
+    /// name of a function exported by the template that wraps this chapter,
+    /// e.g. `appendix` emits `#appendix[#include "..."]`
+    pub template_section: Option<String>,
+
+    /// override `max_width` for the images of this chapter
+    pub max_width: Option<f64>,
+
+    /// override `max_height` for the images of this chapter
+    pub max_height: Option<f64>,
+}
+
+impl FrontMatter {
+    /// Split the front matter block from the chapter content.
+    /// Only a block at the very start of the chapter is treated as front matter,
+    /// a `---` pair further down is a thematic break and stays in the content.
+    pub fn extract(content: &str) -> (Option<FrontMatter>, &str) {
+        let Some((kind, text, end)) = front_matter_block(content) else {
+            return (None, content);
+        };
+        let rest = &content[end..];
+        let front_matter = match kind {
+            MetadataBlockKind::YamlStyle => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+            MetadataBlockKind::PlusesStyle => toml::from_str(&text).map_err(|e| e.to_string()),
+        };
+        match front_matter {
+            Ok(front_matter) => (Some(front_matter), rest),
+            // like `---` `Some Title` `---`, a thematic break and a setext heading, kept as they are
+            Err(e) => {
+                log::warn!("Ignoring invalid front matter, it's kept as content: {}", e);
+                (None, content)
+            }
+        }
+    }
+
+    /// The keys of the `pdf` table that aren't chapter options, like a misspelled `landscpe`.
+    /// They're ignored by [`FrontMatter::extract`], the other tables are free for other tools.
+    pub fn unknown_pdf_options(content: &str) -> Vec<String> {
+        let Some((kind, text, _)) = front_matter_block(content) else {
+            return Vec::new();
+        };
+        let value: Option<serde_json::Value> = match kind {
+            MetadataBlockKind::YamlStyle => serde_yaml::from_str(&text).ok(),
+            MetadataBlockKind::PlusesStyle => toml::from_str(&text).ok(),
+        };
+        let known = serde_json::to_value(ChapterPdfOptions::default()).unwrap_or_default();
+        match value.as_ref().and_then(|value| value.get("pdf")).and_then(|pdf| pdf.as_object()) {
+            Some(pdf) => pdf.keys().filter(|key| known.get(key.as_str()).is_none()).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The chapter content with its front matter block removed
+    pub fn strip(content: &str) -> &str {
+        Self::extract(content).1
+    }
+}
+
+// The kind, text and end offset of the front matter block at the very start of `content`
+fn front_matter_block(content: &str) -> Option<(MetadataBlockKind, String, usize)> {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+    options.insert(Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS);
+
+    let leading_ws = content.len() - content.trim_start().len();
+    let mut kind = None;
+    let mut text = String::new();
+    for (event, range) in Parser::new_ext(content, options).into_offset_iter() {
+        match (event, kind) {
+            (Event::Start(Tag::MetadataBlock(block_kind)), None) if range.start == leading_ws => {
+                kind = Some((block_kind, range.end));
+            }
+            (Event::Text(body), Some(_)) => text.push_str(&body),
+            _ => break,
+        }
+    }
+    kind.map(|(kind, end)| (kind, text, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parameters::to_typst_value;
+
+    #[test]
+    fn test_extract_front_matter() {
+        let content = "---\npdf:\n  landscape: true\n  max_width: 0.5\nmetadata:\n  owner: docs\n---\n# Title\n";
+        let (front_matter, rest) = FrontMatter::extract(content);
+        let front_matter = front_matter.unwrap();
+        assert!(front_matter.pdf.landscape);
+        assert_eq!(front_matter.pdf.max_width, Some(0.5));
+        assert_eq!(to_typst_value(&toml::Value::Table(front_matter.metadata)), "(\"owner\": \"docs\")");
+        assert_eq!(rest.trim(), "# Title");
+
+        let content = "+++\n[pdf]\nskip = true\n+++\nBody";
+        let (front_matter, rest) = FrontMatter::extract(content);
+        assert!(front_matter.unwrap().pdf.skip);
+        assert_eq!(rest.trim(), "Body");
+
+        // a thematic break pair in the middle of a chapter is not front matter
+        let content = "# Title\n\n---\nnot: metadata\n---\n";
+        let (front_matter, rest) = FrontMatter::extract(content);
+        assert!(front_matter.is_none());
+        assert_eq!(rest, content);
+
+        // neither is a block that doesn't parse
+        let content = "---\nSome Title\n---\nBody\n";
+        let (front_matter, rest) = FrontMatter::extract(content);
+        assert!(front_matter.is_none());
+        assert_eq!(rest, content);
+    }
+
+    #[test]
+    fn test_unknown_pdf_options() {
+        let content = "---\npdf:\n  landscpe: true\n  page_break: true\ntitle: Kept for other tools\n---\nBody\n";
+        assert!(FrontMatter::extract(content).0.unwrap().pdf.page_break);
+        assert_eq!(FrontMatter::unknown_pdf_options(content), vec!["landscpe"]);
+        assert_eq!(FrontMatter::unknown_pdf_options("+++\n[pdf]\nskip = true\n+++\n"), Vec::<String>::new());
+        assert_eq!(FrontMatter::unknown_pdf_options("# No front matter\n"), Vec::<String>::new());
+    }
+}
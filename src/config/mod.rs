@@ -1,13 +1,34 @@
-use std::{
-    collections::HashMap,
-    path::PathBuf,
-};
+use std::path::PathBuf;
+
+use indexmap::IndexMap;
 
 pub mod renderer;
 pub mod chapter;
 pub mod book;
+pub mod front_matter;
+pub mod filter;
+pub mod template;
+pub mod parameters;
+pub mod builtin;
+pub mod package;
+pub mod assets;
+pub mod fonts;
+pub mod document;
+pub mod accessibility;
+pub mod output;
+pub mod compile;
+pub mod converter;
+pub mod cache;
+pub mod elements;
+pub mod prelude;
+pub mod escape;
 use serde::{Deserialize, Serialize};
 
+use elements::Element;
+use filter::ChapterFilter;
+use output::{get_default_formats, OutputFormat, SplitMode};
+use template::TemplateEntry;
+
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -16,11 +37,73 @@ pub struct Config {
     #[serde(rename = "template_dir",default = "get_default_template_dir")]
     pub template_dir: String, // the directory of the templates, default is "./typst-template"
 
-    /// the list of template name and template file name. 
+    /// the list of template name and template file name, or a table of per template options.
     /// this tool will generate a pdf file for each template
-    /// if no template is provided, it will generate a default pdf file without template.
+    /// if no template is provided, it will generate a default pdf file with the built-in `default_template`.
     #[serde(rename = "templates", default = "Default::default")]
-    pub templates: HashMap<String, String>, // template name -> template path, the template name will be the name for pdf output
+    pub templates: IndexMap<String, TemplateEntry>, // template name -> template path, the template name will be the name for pdf output
+
+    /// glob patterns, relative to `template_dir`, of the files copied along with the templates.
+    /// the whole template directory is copied if empty, hidden and version control files are always skipped.
+    #[serde(rename = "assets",default = "Default::default")]
+    pub assets: Vec<String>,
+
+    /// the built-in template (`book`, `report` or `article`) used when no `templates` are configured,
+    /// `none` only includes the chapters without any template.
+    #[serde(rename = "default_template",default = "get_default_template")]
+    pub default_template: String,
+
+    /// the local Typst package directory passed to `typst compile --package-path`,
+    /// used for `@local/...` template packages. defaults to the Typst data directory.
+    #[serde(rename = "package_path",default = "Default::default")]
+    pub package_path: Option<String>,
+
+    /// the Typst package cache passed to `typst compile --package-cache-path`,
+    /// used for `@preview/...` template packages. defaults to the Typst cache directory.
+    #[serde(rename = "package_cache_path",default = "Default::default")]
+    pub package_cache_path: Option<String>,
+
+    /// font files or folders passed to `typst compile --font-path`,
+    /// relative to the book root or else to `template_dir`
+    #[serde(rename = "font_paths",default = "Default::default")]
+    pub font_paths: Vec<String>,
+
+    /// only use the fonts from `font_paths` and the ones embedded in typst,
+    /// so the pdf doesn't depend on the fonts installed on the build host
+    #[serde(rename = "ignore_system_fonts",default = "Default::default")]
+    pub ignore_system_fonts: bool,
+
+    /// PDF standards passed to `typst compile --pdf-standard`, comma separated, like `a-2b`.
+    /// the installed typst decides which are supported.
+    /// the book then needs a title, a language and alt text on every image, checked before compiling.
+    #[serde(rename = "pdf_standard",default = "Default::default",deserialize_with = "accessibility::deserialize_pdf_standard")]
+    pub pdf_standard: Option<String>,
+
+    /// the formats to export: `pdf`, `png` and `svg` (one image per page), default is `pdf` only
+    #[serde(rename = "formats",default = "get_default_formats")]
+    pub formats: Vec<OutputFormat>,
+
+    /// the resolution of png page images in pixels per inch, typst defaults to 144
+    #[serde(rename = "ppi",default = "Default::default")]
+    pub ppi: Option<f64>,
+
+    /// `chapter` also compiles every top level chapter, with its sub-chapters, into its own document
+    #[serde(rename = "split",default = "Default::default")]
+    pub split: Option<SplitMode>,
+
+    /// how many `typst compile` runs (templates, split documents, formats) go at the same time,
+    /// default is the number of CPUs
+    #[serde(rename = "jobs",default = "Default::default")]
+    pub jobs: Option<usize>,
+
+    /// converted chapters and their images are kept in `cache_dir` between builds,
+    /// only chapters whose content, images or chapter options changed are converted again
+    #[serde(rename = "cache",default = "get_default_cache")]
+    pub cache: bool,
+
+    /// where the chapter cache lives, relative to the book root. default is `<destination>/cache`
+    #[serde(rename = "cache_dir",default = "Default::default")]
+    pub cache_dir: Option<String>,
 
     /// whether the intermidate typst files for each chapter should be kept or not.
     #[serde(rename = "keep_typst_files",default = "Default::default")]
@@ -28,14 +111,28 @@ pub struct Config {
 
     /// parameter and value pairs for template use.
     /// you can define your value pair that match your template definition.
-    #[serde(rename = "template_parameters",default = "get_default_template_parameters")]
-    pub template_parameters: HashMap<String, String>, // the parameters for the template, default is empty
+    /// values keep their TOML type (string, number, boolean, array, table, date),
+    /// `{ content = "..." }` is converted from markdown to typst content.
+    /// built-in parameters from book.toml, the build date, git and the environment are
+    /// passed to templates that declare them, these values override them.
+    #[serde(rename = "template_parameters",default = "Default::default")]
+    pub template_parameters: IndexMap<String, toml::Value>, // the parameters for the template, default is empty
+
+    /// template parameter name -> environment variable to read its value from.
+    /// `MDBOOK_TYPSTPDF_PARAM_<NAME>` variables are picked up without being listed here.
+    #[serde(rename = "parameter_env",default = "Default::default")]
+    pub parameter_env: IndexMap<String, String>,
 
     /// option multi-lines string that can be imported at the beginning of each chapter. 
     /// it's very useful when you want to add the import statements for popular typst external functions.
     #[serde(rename = "chapter_imports")]
     pub chapter_imports: Option<String>, // it will be a multi-lines string
 
+    /// element -> Typst function called for it instead of the built-in markup, like `quote = "mybox"` for `#mybox[...]`.
+    /// the functions have to be in scope in the chapters, from `chapter_imports`.
+    #[serde(rename = "element_functions",default = "Default::default",deserialize_with = "elements::deserialize_element_functions")]
+    pub element_functions: IndexMap<Element, String>,
+
     /// max_width in a floating number between 0.0 and 1.0 (include). 
     /// this constraint will only be applied when the max height already meets the requirement.
     #[serde(rename = "max_width",default = "Default::default")]
@@ -44,6 +141,19 @@ pub struct Config {
     /// max_height in a floating number between 0.0 and 1.0 (include).
     #[serde(rename = "max_height",default = "Default::default")]
     pub max_height: Option<f64>,
+
+    /// `include`, `exclude` and `audiences` chapter filters applied to every pdf output.
+    /// a template table can override them.
+    #[serde(flatten)]
+    pub chapter_filter: ChapterFilter,
+
+    /// set by `for_template` when a template converts chapters differently from the global options
+    #[serde(skip)]
+    pub chapter_variant: Option<String>,
+
+    /// the build date's seconds since 1970, `SOURCE_DATE_EPOCH` is read when it's not set
+    #[serde(skip)]
+    pub source_date_epoch: Option<i64>,
 }
 
 impl Default for Config {
@@ -51,12 +161,30 @@ impl Default for Config {
         Self {
             // output_dir: get_default_output_dir(),
             template_dir: get_default_template_dir(),
-            templates: HashMap::new(),
+            templates: IndexMap::new(),
+            assets: Vec::new(),
+            default_template: get_default_template(),
+            package_path: None,
+            package_cache_path: None,
+            font_paths: Vec::new(),
+            ignore_system_fonts: false,
+            pdf_standard: None,
+            formats: get_default_formats(),
+            ppi: None,
+            split: None,
+            jobs: None,
+            cache: get_default_cache(),
+            cache_dir: None,
             keep_typst_files: false,
-            template_parameters: get_default_template_parameters(),
+            template_parameters: IndexMap::new(),
+            parameter_env: IndexMap::new(),
             chapter_imports: None,
+            element_functions: IndexMap::new(),
             max_width: None,
             max_height: None,
+            chapter_filter: ChapterFilter::default(),
+            chapter_variant: None,
+            source_date_epoch: None,
         }
     }
 }
@@ -64,6 +192,7 @@ pub const TARGET_TEMPLATE_DIR: &str = "templates";
 pub const TARGET_CHAPTERS_DIR: &str = "chapters";
 pub const TARGET_TYPST_DIR: &str = "typst";
 pub const TARGET_PDF_DIR: &str = "pdf";
+pub const TARGET_CACHE_DIR: &str = "cache";
 pub const BEST_PRACTICE_TEMPLATE:&str = "best_practice_template";
 
 pub const IMAGE_DIR:&str = "__images";
@@ -79,7 +208,10 @@ impl Config {
             root_name
         };
         match template_name {
-            Some(template_name) => format!("{}-{}",root_name,template_name),
+            Some(template_name) => match self.templates.get(template_name).and_then(|template| template.output_name()) {
+                Some(output_name) => output_name.to_string(),
+                None => format!("{}-{}",root_name,template_name),
+            },
             None => root_name.to_string(),
         }
     }
@@ -114,7 +246,15 @@ impl Config {
 
     pub fn get_chapters_dir(&self, ctx: &mdbook::renderer::RenderContext) -> PathBuf {
         let typst_dir = self.get_typst_dir(ctx);
-        typst_dir.join(TARGET_CHAPTERS_DIR)
+        typst_dir.join(self.get_chapters_dir_name())
+    }
+
+    // chapters converted with template specific options live next to the shared ones
+    pub fn get_chapters_dir_name(&self) -> String {
+        match &self.chapter_variant {
+            Some(variant) => format!("{}-{}", TARGET_CHAPTERS_DIR, variant),
+            None => TARGET_CHAPTERS_DIR.to_string(),
+        }
     }
 
     pub fn get_output_dir(&self, ctx: &mdbook::renderer::RenderContext) -> PathBuf {
@@ -126,29 +266,14 @@ impl Config {
 // }
 
 
-fn get_default_template_parameters() -> HashMap<String, String> {
-    let mut result = HashMap::new();
-    // doc_title: "Document Title",
-    // doc_version: "1.0",
-    // abstract: "Document abstract",
-    // doc_author: "Author Name",
-    // author_email: "author@example.com",
-    // doc_date: "January 1, 2023",
-    // software_tested: "Software v1.0",
-    // feedback_email: "feedback@example.com",
-    // reviewers: "Reviewer Names",
-    result.insert("doc_title".to_string(), "Document Title".to_string());
-    result.insert("doc_version".to_string(), "1.0".to_string());
-    result.insert("abstract".to_string(), "Document abstract".to_string());
-    result.insert("doc_author".to_string(), "Author Name".to_string());
-    result.insert("author_email".to_string(), "author@example.com".to_string());
-    result.insert("doc_date".to_string(), "January 1, 2023".to_string());
-    result.insert("software_tested".to_string(), "Software v1.0".to_string());
-    result.insert("feedback_email".to_string(), "feedback@example.com".to_string());
-    result.insert("reviewers".to_string(), "Reviewer Names".to_string());
-    result
-}
-
 fn get_default_template_dir() -> String {
     "./typst-template".to_string()
 }
+
+fn get_default_template() -> String {
+    "book".to_string()
+}
+
+fn get_default_cache() -> bool {
+    true
+}
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+use super::TARGET_PDF_DIR;
+
+/// A file format `typst compile` exports to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Pdf,
+    /// one image per page, resolution from `ppi`
+    Png,
+    /// one image per page
+    Svg,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Png => "png",
+            OutputFormat::Svg => "svg",
+        }
+    }
+
+    /// the folder under the output directory the files go to
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            OutputFormat::Pdf => TARGET_PDF_DIR,
+            OutputFormat::Png => "png",
+            OutputFormat::Svg => "svg",
+        }
+    }
+
+    /// the output file name, page images get the zero padded page number
+    pub fn file_name(&self, document_name: &str) -> String {
+        match self {
+            OutputFormat::Pdf => format!("{}.pdf", document_name),
+            _ => format!("{}-{{0p}}.{}", document_name, self.extension()),
+        }
+    }
+}
+
+pub fn get_default_formats() -> Vec<OutputFormat> {
+    vec![OutputFormat::Pdf]
+}
+
+/// Additional documents compiled next to the whole book
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitMode {
+    /// one document per top level chapter, with its sub-chapters
+    Chapter,
+}
+
+/// `<book>-<nn>-<chapter path>`, e.g. `handbook-02-guide-setup` for the second top level chapter `guide/setup.md`
+pub fn split_document_name(book_name: &str, index: usize, chapter: &mdbook::book::Chapter) -> String {
+    let chapter_name = match &chapter.source_path {
+        Some(path) => path.with_extension("").to_string_lossy().to_string(),
+        None => chapter.name.clone(),
+    };
+    let chapter_name: String = chapter_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    format!("{}-{:02}-{}", book_name, index, chapter_name.trim_matches('-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_names() {
+        assert_eq!(OutputFormat::Pdf.file_name("book"), "book.pdf");
+        assert_eq!(OutputFormat::Png.file_name("book"), "book-{0p}.png");
+        let formats: Vec<OutputFormat> = toml::Value::from(vec!["pdf", "svg"]).try_into().unwrap();
+        assert_eq!(formats, vec![OutputFormat::Pdf, OutputFormat::Svg]);
+
+        let chapter = mdbook::book::Chapter::new("Setup", String::new(), "guide/setup.md", Vec::new());
+        assert_eq!(split_document_name("handbook", 2, &chapter), "handbook-02-guide-setup");
+    }
+}
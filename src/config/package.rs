@@ -0,0 +1,130 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use super::Config;
+
+/// A Typst package reference like `@local/corp-book:1.2.0`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackageSpec {
+    pub namespace: String,
+    pub name: String,
+    pub version: semver::Version,
+}
+
+impl PackageSpec {
+    /// Parse `@namespace/name:version`, `None` when `spec` isn't a package reference
+    pub fn parse(spec: &str) -> Option<anyhow::Result<PackageSpec>> {
+        let spec = spec.strip_prefix('@')?;
+        Some(Self::parse_parts(spec).ok_or_else(|| anyhow::anyhow!("invalid package @{}, expected @namespace/name:version", spec)))
+    }
+
+    fn parse_parts(spec: &str) -> Option<PackageSpec> {
+        let (namespace, rest) = spec.split_once('/')?;
+        let (name, version) = rest.split_once(':')?;
+        if namespace.is_empty() || name.is_empty() {
+            return None;
+        }
+        Some(PackageSpec { namespace: namespace.to_string(), name: name.to_string(), version: version.parse().ok()? })
+    }
+
+    fn relative_dir(&self) -> PathBuf {
+        PathBuf::from(&self.namespace).join(&self.name).join(self.version.to_string())
+    }
+}
+
+impl fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}/{}:{}", self.namespace, self.name, self.version)
+    }
+}
+
+#[derive(Deserialize)]
+struct PackageManifest {
+    package: PackageInfo,
+}
+
+#[derive(Deserialize)]
+struct PackageInfo {
+    entrypoint: String,
+}
+
+/// The entry point file of a package or template directory, read from its `typst.toml`
+pub fn read_entrypoint(dir: &Path) -> anyhow::Result<String> {
+    let manifest_file = dir.join("typst.toml");
+    let manifest = std::fs::read_to_string(&manifest_file)
+        .map_err(|e| anyhow::anyhow!("failed to read {}: {}", manifest_file.display(), e))?;
+    let manifest: PackageManifest =
+        toml::from_str(&manifest).map_err(|e| anyhow::anyhow!("invalid {}: {}", manifest_file.display(), e))?;
+    Ok(manifest.package.entrypoint.replace('\\', "/"))
+}
+
+impl Config {
+    fn resolve_path(&self, path: &str, ctx: &mdbook::renderer::RenderContext) -> PathBuf {
+        let path = Path::new(path);
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            ctx.root.join(path)
+        }
+    }
+
+    /// `package_path` or the Typst default for local packages (`<data dir>/typst/packages`)
+    pub fn get_package_path(&self, ctx: &mdbook::renderer::RenderContext) -> Option<PathBuf> {
+        match &self.package_path {
+            Some(path) => Some(self.resolve_path(path, ctx)),
+            None => dirs::data_dir().map(|dir| dir.join("typst").join("packages")),
+        }
+    }
+
+    /// `package_cache_path` or the Typst default for downloaded packages (`<cache dir>/typst/packages`)
+    pub fn get_package_cache_path(&self, ctx: &mdbook::renderer::RenderContext) -> Option<PathBuf> {
+        match &self.package_cache_path {
+            Some(path) => Some(self.resolve_path(path, ctx)),
+            None => dirs::cache_dir().map(|dir| dir.join("typst").join("packages")),
+        }
+    }
+
+    /// The folder of a package, looked up like Typst does:
+    /// the package path for any namespace, then the package cache for `@preview`
+    pub fn get_package_dir(&self, spec: &PackageSpec, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<PathBuf> {
+        let mut candidates = Vec::new();
+        if let Some(path) = self.get_package_path(ctx) {
+            candidates.push(path.join(spec.relative_dir()));
+        }
+        if spec.namespace == "preview" {
+            if let Some(path) = self.get_package_cache_path(ctx) {
+                candidates.push(path.join(spec.relative_dir()));
+            }
+        }
+        match candidates.iter().find(|dir| dir.join("typst.toml").is_file()) {
+            Some(dir) => Ok(dir.clone()),
+            None => {
+                let searched: Vec<String> = candidates.iter().map(|dir| dir.display().to_string()).collect();
+                Err(anyhow::anyhow!(
+                    "package {} not found in {}, set package_path or package_cache_path (`typst compile` downloads @preview packages to the cache)",
+                    spec, searched.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_spec() {
+        let spec = PackageSpec::parse("@local/corp-book:1.2.0").unwrap().unwrap();
+        assert_eq!(spec.namespace, "local");
+        assert_eq!(spec.name, "corp-book");
+        assert_eq!(spec.relative_dir(), Path::new("local/corp-book/1.2.0"));
+        assert_eq!(spec.to_string(), "@local/corp-book:1.2.0");
+        assert!(PackageSpec::parse("report.typ").is_none());
+        assert!(PackageSpec::parse("@preview/tablex").unwrap().is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use std::{path::Path, process::Command};
+
+use chrono::Datelike;
+use indexmap::IndexMap;
+
+use super::{template::TemplateSignature, Config};
+
+/// environment variables with this prefix become template parameters,
+/// e.g. `MDBOOK_TYPSTPDF_PARAM_DOC_VERSION=2.1` sets `doc_version`
+pub const PARAMETER_ENV_PREFIX: &str = "MDBOOK_TYPSTPDF_PARAM_";
+
+/// Values for the parameters of a template entry function, by parameter name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TemplateArguments {
+    pub positional: Vec<(String, toml::Value)>,
+    pub named: Vec<(String, toml::Value)>,
+}
+
+/// Typst string literal for `text`, escaping everything that is special inside `"..."`
+pub fn typst_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+// Typst `datetime(..)` call for a TOML date, time or date-time.
+// Typst datetimes have no timezone, so an offset is dropped.
+fn typst_datetime(datetime: &toml::value::Datetime) -> String {
+    let mut fields = Vec::new();
+    if let Some(date) = &datetime.date {
+        fields.push(format!("year: {}, month: {}, day: {}", date.year, date.month, date.day));
+    }
+    if let Some(time) = &datetime.time {
+        fields.push(format!("hour: {}, minute: {}, second: {}", time.hour, time.minute, time.second));
+    }
+    if datetime.offset.is_some() {
+        log::warn!("Typst datetimes have no timezone, the offset of {} is ignored", datetime);
+    }
+    format!("datetime({})", fields.join(", "))
+}
+
+/// Convert a TOML value into the equivalent Typst literal.
+/// Strings, numbers, booleans, arrays, tables (as dictionaries) and dates are supported.
+pub(crate) fn to_typst_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => typst_string(s),
+        toml::Value::Integer(i) => i.to_string(),
+        toml::Value::Float(f) if f.is_nan() => "float.nan".to_string(),
+        toml::Value::Float(f) if f.is_infinite() => if *f > 0.0 { "float.inf" } else { "-float.inf" }.to_string(),
+        toml::Value::Float(f) => format!("{:?}", f),
+        toml::Value::Boolean(b) => b.to_string(),
+        toml::Value::Datetime(d) => typst_datetime(d),
+        toml::Value::Array(items) => typst_array(items.iter().map(to_typst_value).collect()),
+        toml::Value::Table(table) => {
+            typst_dictionary(table.iter().map(|(key, value)| (key.as_str(), to_typst_value(value))).collect())
+        }
+    }
+}
+
+fn typst_array(items: Vec<String>) -> String {
+    // a single element array needs a trailing comma in Typst
+    if items.len() == 1 {
+        format!("({},)", items[0])
+    } else {
+        format!("({})", items.join(", "))
+    }
+}
+
+fn typst_dictionary(items: Vec<(&str, String)>) -> String {
+    if items.is_empty() {
+        return "(:)".to_string();
+    }
+    let items: Vec<String> = items
+        .into_iter()
+        .map(|(key, value)| format!("{}: {}", typst_string(key), value))
+        .collect();
+    format!("({})", items.join(", "))
+}
+
+// `{ content = "..." }` marks a Markdown value that is converted to Typst content
+fn as_markdown_content(value: &toml::Value) -> Option<&str> {
+    match value {
+        toml::Value::Table(table) if table.len() == 1 => table.get("content").and_then(|content| content.as_str()),
+        _ => None,
+    }
+}
+
+/// `SOURCE_DATE_EPOCH` (seconds since 1970, UTC), set for reproducible builds
+pub fn source_date_epoch() -> Option<i64> {
+    std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.trim().parse::<i64>().ok())
+}
+
+/// The date of the build, fixed by `epoch` or today
+pub fn build_date(epoch: Option<i64>) -> chrono::NaiveDate {
+    match epoch.and_then(|epoch| chrono::DateTime::from_timestamp(epoch, 0)) {
+        Some(date) => date.date_naive(),
+        None => chrono::Local::now().date_naive(),
+    }
+}
+
+fn git_output(root: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(root).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+impl Config {
+    /// The date of the build, from `source_date_epoch` or else `SOURCE_DATE_EPOCH`
+    pub fn build_date(&self) -> chrono::NaiveDate {
+        build_date(self.source_date_epoch.or_else(source_date_epoch))
+    }
+
+    /// Parameters derived from `[book]` in book.toml, the build date, the local git repository and environment variables.
+    ///
+    /// They're only passed to templates that declare them, and `template_parameters` overrides them.
+    pub fn get_builtin_parameters(&self, ctx: &mdbook::renderer::RenderContext) -> IndexMap<String, toml::Value> {
+        let mut result = IndexMap::new();
+        let book = &ctx.config.book;
+        if let Some(title) = &book.title {
+            result.insert("book_title".to_string(), title.as_str().into());
+            result.insert("doc_title".to_string(), title.as_str().into());
+        }
+        if !book.authors.is_empty() {
+            let authors: Vec<toml::Value> = book.authors.iter().map(|author| author.as_str().into()).collect();
+            result.insert("book_authors".to_string(), toml::Value::Array(authors));
+            result.insert("doc_author".to_string(), book.authors.join(", ").into());
+        }
+        if let Some(description) = &book.description {
+            result.insert("book_description".to_string(), description.as_str().into());
+            result.insert("abstract".to_string(), description.as_str().into());
+        }
+        if let Some(language) = &book.language {
+            result.insert("book_language".to_string(), language.as_str().into());
+        }
+
+        let today = self.build_date();
+        result.insert("doc_date".to_string(), today.format("%Y-%m-%d").to_string().into());
+        result.insert(
+            "build_date".to_string(),
+            toml::Value::Datetime(toml::value::Datetime {
+                date: Some(toml::value::Date { year: today.year() as u16, month: today.month() as u8, day: today.day() as u8 }),
+                time: None,
+                offset: None,
+            }),
+        );
+
+        // read from the local repository only, no fetch
+        if let Some(commit) = git_output(&ctx.root, &["rev-parse", "HEAD"]) {
+            result.insert("git_commit_short".to_string(), commit.chars().take(7).collect::<String>().into());
+            result.insert("git_commit".to_string(), commit.into());
+        }
+        if let Some(describe) = git_output(&ctx.root, &["describe", "--tags", "--always", "--dirty"]) {
+            result.insert("git_describe".to_string(), describe.into());
+        }
+
+        let mut variables: Vec<(String, String)> = std::env::vars().collect();
+        variables.sort();
+        for (key, value) in variables {
+            if let Some(name) = key.strip_prefix(PARAMETER_ENV_PREFIX) {
+                result.insert(name.to_lowercase(), value.into());
+            }
+        }
+        for (name, variable) in &self.parameter_env {
+            match std::env::var(variable) {
+                Ok(value) => {
+                    result.insert(name.clone(), value.into());
+                }
+                Err(_) => log::debug!("environment variable {} for parameter {} is not set", variable, name),
+            }
+        }
+        result
+    }
+
+    /// `template_parameters` with the overrides mdBook reads from `MDBOOK_OUTPUT__TYPSTPDF__TEMPLATE_PARAMETERS__<NAME>`.
+    /// mdBook turns those into `template-parameters.<name>` with every `_` as `-`, the `-` are turned back.
+    pub fn get_template_parameters(&self, ctx: &mdbook::renderer::RenderContext) -> IndexMap<String, toml::Value> {
+        let mut parameters = self.template_parameters.clone();
+        let key = format!("{}.template-parameters", crate::Renderer::CONFIG_KEY);
+        match ctx.config.get_deserialized_opt::<IndexMap<String, toml::Value>, _>(&key) {
+            Ok(overrides) => {
+                for (name, value) in overrides.unwrap_or_default() {
+                    parameters.insert(name.replace('-', "_"), value);
+                }
+            }
+            Err(e) => log::warn!("ignoring {}: {:#}", key, e),
+        }
+        parameters
+    }
+
+    /// The arguments passed to a template entry function.
+    ///
+    /// Built-in parameters are only passed when declared, `template_parameters` the template
+    /// doesn't declare are reported and left out, and a required positional parameter without value is an error.
+    pub fn get_template_arguments(&self, template_name: &str, signature: &TemplateSignature, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<TemplateArguments> {
+        let mut parameters = self.get_builtin_parameters(ctx);
+        let explicit = self.get_template_parameters(ctx);
+        parameters.extend(explicit.clone());
+
+        let mut arguments = TemplateArguments::default();
+        for name in signature.required() {
+            if !typst_syntax::is_ident(name) {
+                return Err(anyhow::anyhow!(
+                    "template {} requires the positional parameter {} of {}, which isn't a plain name and can't be set from template_parameters",
+                    template_name, name, signature.function
+                ));
+            }
+            match parameters.get(name) {
+                Some(value) => arguments.positional.push((name.clone(), value.clone())),
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "template {} requires the parameter {} of {}, add it to template_parameters",
+                        template_name, name, signature.function
+                    ));
+                }
+            }
+        }
+
+        let mut names: Vec<&String> = parameters.keys().collect();
+        names.sort();
+        for name in names {
+            if signature.required().contains(name) {
+                continue;
+            }
+            // `..args` takes any name, but the book document passes them as `name: value`
+            if signature.accepts_any && !signature.named.contains(name) && explicit.contains_key(name) && !typst_syntax::is_ident(name) {
+                log::warn!("template {} ({}): the parameter {:?} isn't a Typst name, it's not passed", template_name, signature.function, name);
+                continue;
+            }
+            if signature.named.contains(name) || (signature.accepts_any && explicit.contains_key(name)) {
+                arguments.named.push((name.clone(), parameters[name].clone()));
+            } else if explicit.contains_key(name) {
+                log::warn!("template {} ({}) doesn't declare the parameter {}, it's not passed", template_name, signature.function, name);
+            }
+        }
+        Ok(arguments)
+    }
+
+    /// Convert a `template_parameters` value into a Typst expression.
+    ///
+    /// Besides plain TOML values, `{ content = "Markdown text" }` is converted
+    /// from Markdown and passed as a content block, e.g. for a multi-paragraph abstract.
+    pub fn template_parameter_to_typst(&self, value: &toml::Value, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<String> {
+        if let Some(markdown) = as_markdown_content(value) {
+            // the parameters live in the book level typst file, images are resolved from there
+            let typst_dir = self.get_typst_dir(ctx);
+            let content = self.convert_markdown(markdown, self.max_width, self.max_height, &typst_dir, ctx);
+            return Ok(format!("[{}]", content.trim()));
+        }
+        match value {
+            toml::Value::Array(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.template_parameter_to_typst(item, ctx))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Ok(typst_array(items))
+            }
+            toml::Value::Table(table) => {
+                let mut items = Vec::new();
+                for (key, value) in table {
+                    items.push((key.as_str(), self.template_parameter_to_typst(value, ctx)?));
+                }
+                Ok(typst_dictionary(items))
+            }
+            value => Ok(to_typst_value(value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_typst_value() {
+        let value: toml::Table = toml::from_str(
+            r#"
+            title = "Say \"hi\"\n\tC:\\temp"
+            draft = true
+            copies = 3
+            ratio = 0.5
+            authors = ["Ann"]
+            date = 2025-02-26
+            contact = { name = "Ann", mail = "ann@example.com" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(to_typst_value(&value["title"]), r#""Say \"hi\"\n\tC:\\temp""#);
+        assert_eq!(to_typst_value(&value["draft"]), "true");
+        assert_eq!(to_typst_value(&value["copies"]), "3");
+        assert_eq!(to_typst_value(&value["ratio"]), "0.5");
+        assert_eq!(to_typst_value(&value["authors"]), r#"("Ann",)"#);
+        assert_eq!(to_typst_value(&value["date"]), "datetime(year: 2025, month: 2, day: 26)");
+        assert_eq!(to_typst_value(&value["contact"]), r#"("name": "Ann", "mail": "ann@example.com")"#);
+        assert_eq!(as_markdown_content(&toml::from_str::<toml::Value>("content = \"*x*\"").unwrap()), Some("*x*"));
+    }
+
+    #[test]
+    fn test_mdbook_environment_overrides() {
+        // what mdBook makes of MDBOOK_OUTPUT__TYPSTPDF__TEMPLATE_PARAMETERS__DOC_VERSION=2.1
+        let mut book_config = mdbook::Config::default();
+        book_config.set("output.typstpdf.template-parameters.doc-version", 2.1).unwrap();
+        let ctx = mdbook::renderer::RenderContext::new("/book", mdbook::book::Book::new(), book_config, "/book/out");
+
+        let config = Config { template_parameters: toml::from_str("doc_version = \"1.0\"\ndraft = true").unwrap(), ..Config::default() };
+        let parameters = config.get_template_parameters(&ctx);
+        assert_eq!(parameters["doc_version"].as_float(), Some(2.1));
+        assert_eq!(parameters["draft"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_template_argument_names() {
+        let ctx = mdbook::renderer::RenderContext::new("/book", mdbook::book::Book::new(), mdbook::Config::default(), "/book/out");
+        let config = Config { template_parameters: toml::from_str("title = \"T\"\n\"bad key\" = 1\ndoc-version = 2").unwrap(), ..Config::default() };
+
+        let signature = TemplateSignature::parse("#let book(title, ..args, body) = body", None).unwrap();
+        let arguments = config.get_template_arguments("book", &signature, &ctx).unwrap();
+        assert_eq!(arguments.positional[0].0, "title");
+        let named: Vec<&str> = arguments.named.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(named, vec!["doc-version"]);
+
+        let signature = TemplateSignature::parse("#let book((title, subtitle), body) = body", None).unwrap();
+        let error = config.get_template_arguments("book", &signature, &ctx).unwrap_err().to_string();
+        assert!(error.contains("(title, subtitle) of book, which isn't a plain name"), "{}", error);
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use indexmap::IndexMap;
+
+use super::{elements::Element, template::TemplateSignature, Config};
+
+/// The prelude file in the typst folder, chapters and book documents import it as `/mdbook-prelude.typ`
+pub const PRELUDE_FILE: &str = "mdbook-prelude.typ";
+
+/// The Typst source of the prelude
+pub const PRELUDE: &str = include_str!("../templates/prelude.typ");
+
+/// The prelude functions and the elements they render
+pub const PRELUDE_FUNCTIONS: &[(Element, &str)] = &[
+    (Element::Rule, "mdbook-rule"),
+    (Element::Image, "mdbook-image"),
+    (Element::Task, "mdbook-task"),
+];
+
+/// The `element_functions` calling the prelude, for a [`crate::Converter`] whose output imports [`PRELUDE`]
+pub fn prelude_functions() -> IndexMap<Element, String> {
+    PRELUDE_FUNCTIONS.iter().map(|(element, function)| (*element, function.to_string())).collect()
+}
+
+/// `#import "/mdbook-prelude.typ": *`
+pub fn prelude_import() -> String {
+    format!("#import \"/{}\": *\n", PRELUDE_FILE)
+}
+
+/// The prelude functions a template defines, its documents use them instead of the defaults
+pub fn get_shadowed_functions(template_source: &Path) -> anyhow::Result<Vec<String>> {
+    let source = std::fs::read_to_string(template_source)?;
+    let functions = TemplateSignature::parse_functions(&source).map_err(|e| anyhow::anyhow!("{}: {}", template_source.display(), e))?;
+    Ok(PRELUDE_FUNCTIONS
+        .iter()
+        .map(|(_, function)| function.to_string())
+        .filter(|function| functions.iter().any(|defined| &defined.function == function))
+        .collect())
+}
+
+impl Config {
+    /// the prelude functions, with `element_functions` over them
+    pub fn get_element_functions(&self) -> IndexMap<Element, String> {
+        let mut functions = prelude_functions();
+        functions.extend(self.element_functions.clone());
+        functions
+    }
+
+    pub fn write_prelude(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+        let typst_dir = self.get_typst_dir(ctx);
+        std::fs::create_dir_all(&typst_dir)?;
+        std::fs::write(typst_dir.join(PRELUDE_FILE), PRELUDE)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prelude() {
+        // the prelude defines every function the chapters call
+        let functions = TemplateSignature::parse_functions(PRELUDE).unwrap();
+        for (_, function) in PRELUDE_FUNCTIONS {
+            assert!(functions.iter().any(|defined| &defined.function == function), "{} is missing", function);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("template.typ");
+        std::fs::write(&template, "#let mdbook-rule() = line(length: 50%)\n#let doc(body) = body\n").unwrap();
+        assert_eq!(get_shadowed_functions(&template).unwrap(), vec!["mdbook-rule"]);
+
+        let config: Config = toml::from_str("[element_functions]\nrule = \"divider\"\nquote = \"mybox\"").unwrap();
+        let functions = config.get_element_functions();
+        assert_eq!(functions[&Element::Rule], "divider");
+        assert_eq!(functions[&Element::Image], "mdbook-image");
+        assert_eq!(functions[&Element::Quote], "mybox");
+    }
+}
@@ -1,7 +1,7 @@
 
 use std::fs;
 
-use super::Config;
+use super::{assets::copy_tree, builtin::BuiltinTemplate, template::TemplateEntry, Config};
 
 impl Config {
     pub fn prepare_chapter_dir(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
@@ -13,36 +13,6 @@ impl Config {
         Ok(())
     }
 
-    pub fn prepare_template_images(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
-        let target_template_dir = self.get_typst_templates_dir(ctx);
-        let source_template_dir = self.get_template_dir(ctx);
-        let images_dir = source_template_dir.join("images");
-        if images_dir.exists() {
-            // copy the folder to the typst_dir
-            fs::create_dir_all(target_template_dir.join("images"))?;
-            let entries = fs::read_dir(&images_dir)?;
-            for entry in entries {
-                let entry = entry?;
-                let path = entry.path();
-                let dest = target_template_dir.join("images").join(path.file_name().unwrap());
-                if path.is_file() {
-                    fs::copy(&path, &dest)?;
-                } else if path.is_dir() {
-                    fs::create_dir_all(&dest)?;
-                    let subentries = fs::read_dir(&path)?;
-                    for subentry in subentries {
-                        let subentry = subentry?;
-                        let subpath = subentry.path();
-                        fs::copy(&subpath, dest.join(subpath.file_name().unwrap()))?;
-                    }
-                }
-            }
-            log::debug!("copied images folder from {} to {}", images_dir.display(), target_template_dir.join("images").display());
-        }
-
-        Ok(())
-    }
-
     pub fn prepare_templates(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
         
         let target_template_dir = self.get_typst_templates_dir(ctx);
@@ -51,47 +21,83 @@ impl Config {
         let source_template_dir = self.get_template_dir(ctx);
         log::debug!("template_dir value:{},template_dir folder: {}", self.template_dir, source_template_dir.display());
 
-        self.prepare_template_images(ctx)?;
+        self.prepare_template_assets(ctx)?;
         
         // check the templates hashmap
-        for (name, file_name) in &self.templates {
-            let template_file = source_template_dir.join(file_name);
-            if !template_file.exists() {
-                return Err(anyhow::anyhow!("template file {} not found", template_file.display()));
-            }
-            // get the file name from the template_file 
-            let file_name = template_file.file_name().unwrap().to_str().unwrap();
-            let dst = target_template_dir.join(file_name);
-            fs::copy(&template_file, &dst)?;
-            log::debug!("{},copied template file {} to {}", name, template_file.display(), dst.display());
+        for (name, template) in &self.templates {
+            self.copy_template(name, template, ctx)?;
+        }
+        if let Some(template) = self.get_builtin_default_template()? {
+            self.copy_template("default", &template, ctx)?;
         }
 
         Ok(())
     }
 
-    pub fn renderer(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+    fn copy_template(&self, name: &str, template: &TemplateEntry, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+        let dst = self.get_typst_templates_dir(ctx).join(template.target_file_name()?);
+        // built-in templates are written from the binary, they don't need a template_dir
+        if let Some(builtin) = template.builtin() {
+            fs::write(&dst, BuiltinTemplate::find(builtin)?.source)?;
+            log::debug!("{},wrote built-in template {} to {}", name, builtin, dst.display());
+            return Ok(());
+        }
+        // packages are read by typst from the package path or cache
+        if let Some(spec) = template.package() {
+            let dir = self.get_package_dir(&spec?, ctx)?;
+            log::debug!("{},using template package from {}", name, dir.display());
+            return Ok(());
+        }
+        let template_file = self.get_template_dir(ctx).join(template.file());
+        // a template directory is copied as a whole, with its modules, fonts and data files
+        if template_file.is_dir() {
+            copy_tree(&template_file, &dst, None)?;
+            log::debug!("{},copied template folder {} to {}", name, template_file.display(), dst.display());
+            return Ok(());
+        }
+        if !template_file.exists() {
+            return Err(anyhow::anyhow!("template file {} not found", template_file.display()));
+        }
+        fs::copy(&template_file, &dst)?;
+        log::debug!("{},copied template file {} to {}", name, template_file.display(), dst.display());
+        Ok(())
+    }
+
+    /// Write the typst files of the book, without compiling them.
+    /// Returns the names of the book level documents to compile.
+    pub fn generate_typst(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<Vec<String>> {
         let book = &ctx.book;
         log::debug!("book items:{}", book.sections.len());
         // steps
         // 1. create "typst" folder under the typst_pdf_dir for hosting all typst files
         // 2. copy all template typst files to the "typst" folder
-        // 3. copy the images folder to support typst template to the "typst" folder
+        // 3. copy the template assets (images, modules, fonts, data files) to the "typst" folder
         // 4. copy code block template to the "typst" folder
         self.prepare_templates(ctx)?;
+        self.write_prelude(ctx)?;
         
         // 5. create chapter folder under the typst_pdf_dir/typst/
-        self.prepare_chapter_dir(ctx)?;
-
-        // 6. convert each chapter to typst file
+        // 6. convert each chapter to typst file,
+        //    once more for every template that converts chapters with its own options
         let mut chapter_file_list = Vec::new();
-        self.convert_chapters(&mut chapter_file_list, ctx)?;
+        for variant in self.get_chapter_variants() {
+            variant.prepare_chapter_dir(ctx)?;
+            variant.convert_chapters(&mut chapter_file_list, ctx)?;
+        }
         log::debug!("chapter_file_list: {:?}", chapter_file_list);
 
         // 7. convert the book to a typst file
-        self.convert_book(&mut chapter_file_list,ctx)?;
+        self.convert_book(&mut chapter_file_list,ctx)
+    }
 
-        // 8. convert the book to pdf
-        self.convert_book_to_pdf(ctx)?;
+    pub fn renderer(&self, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<()> {
+        // report everything standard compliant pdf output lacks before any work is done
+        self.check_pdf_standard(ctx)?;
+
+        let documents = self.generate_typst(ctx)?;
+
+        // 8. convert the book to pdf, and the other formats
+        self.convert_book_to_pdf(&documents, ctx)?;
 
         if !self.keep_typst_files {
             // 9. remove the typst folder
@@ -104,4 +110,93 @@ impl Config {
         
         Ok(())
     }
+
+    /// Build again after a change, with the typst files of the previous build kept:
+    /// the templates are only copied when `templates` changed and the chapters only converted
+    /// when `chapters` changed, the book documents are written and compiled every time.
+    pub fn rebuild(&self, ctx: &mdbook::renderer::RenderContext, templates: bool, chapters: bool) -> anyhow::Result<()> {
+        self.check_pdf_standard(ctx)?;
+        if templates {
+            self.prepare_templates(ctx)?;
+            self.write_prelude(ctx)?;
+        }
+        if chapters {
+            let mut chapter_file_list = Vec::new();
+            for variant in self.get_chapter_variants() {
+                variant.prepare_chapter_dir(ctx)?;
+                variant.convert_chapters(&mut chapter_file_list, ctx)?;
+            }
+        }
+        let documents = self.convert_book(&mut Vec::new(), ctx)?;
+        self.convert_book_to_pdf(&documents, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, path::Path};
+
+    use mdbook::{book::Chapter, BookItem};
+    use pulldown_cmark::{Event, Tag};
+
+    use super::*;
+    use crate::config::{
+        assets::copy_tree,
+        converter::{markdown_options, remote_image_file_name},
+        IMAGE_DIR,
+    };
+
+    fn generate_test_book(root: &Path, destination: &Path) -> BTreeMap<String, Vec<u8>> {
+        let book = mdbook::MDBook::load(root).unwrap();
+        let ctx = mdbook::renderer::RenderContext::new(root, book.book, book.config, destination);
+        let config: Config = ctx.config.get_deserialized_opt(crate::Renderer::CONFIG_KEY).unwrap().unwrap_or_default();
+        // 2021-01-01, the build date every build gets
+        let config = Config { source_date_epoch: Some(1609459200), ..config };
+        // the remote images count as downloaded already, the test stays offline
+        for item in ctx.book.iter() {
+            let BookItem::Chapter(Chapter { content, path: Some(path), .. }) = item else { continue };
+            let image_dir = config.get_cached_chapters_dir(&ctx).join(path.parent().unwrap()).join(IMAGE_DIR);
+            fs::create_dir_all(&image_dir).unwrap();
+            for event in pulldown_cmark::Parser::new_ext(content, markdown_options()) {
+                if let Event::Start(Tag::Image { dest_url, .. }) = event {
+                    if dest_url.starts_with("http") {
+                        fs::write(image_dir.join(remote_image_file_name(&dest_url)), "png").unwrap();
+                    }
+                }
+            }
+        }
+        config.generate_typst(&ctx).unwrap();
+
+        let mut files = BTreeMap::new();
+        for entry in walkdir::WalkDir::new(destination) {
+            let entry = entry.unwrap();
+            if entry.path().extension().is_some_and(|extension| extension == "typ") {
+                let relative = entry.path().strip_prefix(destination).unwrap().to_string_lossy().to_string();
+                files.insert(relative, fs::read(entry.path()).unwrap());
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn test_reproducible_typst_files() {
+        // a copy, the sample keeps no build output
+        let root = tempfile::tempdir().unwrap();
+        copy_tree(&Path::new(env!("CARGO_MANIFEST_DIR")).join("samples/test_book"), root.path(), None).unwrap();
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let first = generate_test_book(root.path(), first.path());
+        let second = generate_test_book(root.path(), second.path());
+        assert!(first.len() > 3);
+        assert_eq!(first.keys().collect::<Vec<_>>(), second.keys().collect::<Vec<_>>());
+        for (file, content) in &first {
+            assert!(content == &second[file], "{} differs between builds", file);
+        }
+        let images = String::from_utf8_lossy(&first["typst/chapters/individual/image.typ"]);
+        assert!(images.contains("remote_img_") && !images.contains("placeholder_"));
+        // the book document is named after the book folder
+        let book = format!("typst/{}.typ", root.path().file_name().unwrap().to_string_lossy());
+        let book = String::from_utf8_lossy(&first[&book]);
+        assert!(book.contains("date: datetime(year: 2021, month: 1, day: 1)"), "{}", book);
+    }
 }
@@ -0,0 +1,417 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use typst_syntax::ast::{self, AstNode};
+
+use super::{
+    builtin::{BuiltinTemplate, BUILTIN_TEMPLATE_PREFIX},
+    elements::{deserialize_element_functions, Element},
+    filter::ChapterFilter,
+    package::{read_entrypoint, PackageSpec},
+    Config, BEST_PRACTICE_TEMPLATE, TARGET_TEMPLATE_DIR,
+};
+
+/// A `templates` entry, either just the template file name:
+///
+/// ```toml
+/// [output.typstpdf.templates]
+/// screen = "screen.typ"
+/// ```
+///
+/// or a table with options that override the global ones for this template:
+///
+/// ```toml
+/// [output.typstpdf.templates.print]
+/// file = "print.typ"
+/// output_name = "handbook-print"
+/// max_width = 0.8
+/// exclude = ["internal/**"]
+///
+/// [output.typstpdf.templates.print.parameters]
+/// doc_version = "2.0-print"
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum TemplateEntry {
+    File(String),
+    Table(Box<TemplateConfig>),
+}
+
+/// A template table, unknown keys are an error so a typo doesn't go unnoticed
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+    /// the template file or directory, relative to `template_dir`,
+    /// a Typst package like `@local/corp-book:1.2.0`, or `builtin:<name>` for a built-in template
+    pub file: String,
+
+    /// the template function wrapping the document, found from the template file when not given
+    pub entry: Option<String>,
+
+    /// merged over the global `template_parameters`, values here win
+    #[serde(default)]
+    pub parameters: IndexMap<String, toml::Value>,
+
+    /// replaces the global `chapter_imports`
+    pub chapter_imports: Option<String>,
+
+    /// merged over the global `element_functions`
+    #[serde(default, deserialize_with = "deserialize_element_functions")]
+    pub element_functions: IndexMap<Element, String>,
+
+    /// the name of the generated typst and pdf files, without extension.
+    /// defaults to `<book>-<template name>`
+    pub output_name: Option<String>,
+
+    /// replaces the global `max_width`
+    pub max_width: Option<f64>,
+
+    /// replaces the global `max_height`
+    pub max_height: Option<f64>,
+
+    /// `include`, `exclude` and `audiences` for this template, empty fields fall back to the global ones
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    #[serde(default)]
+    pub audiences: Vec<String>,
+}
+
+impl TemplateConfig {
+    pub fn chapter_filter(&self) -> ChapterFilter {
+        ChapterFilter { include: self.include.clone(), exclude: self.exclude.clone(), audiences: self.audiences.clone() }
+    }
+}
+
+// a string is the template file, a table goes through `TemplateConfig` so its errors name the bad key
+impl<'de> Deserialize<'de> for TemplateEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = TemplateEntry;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a template file or a table of template options")
+            }
+
+            fn visit_str<E: de::Error>(self, file: &str) -> Result<TemplateEntry, E> {
+                Ok(TemplateEntry::File(file.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<TemplateEntry, A::Error> {
+                let template = TemplateConfig::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(TemplateEntry::Table(Box::new(template)))
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+impl TemplateEntry {
+    pub fn file(&self) -> &str {
+        match self {
+            TemplateEntry::File(file) => file,
+            TemplateEntry::Table(template) => &template.file,
+        }
+    }
+
+    pub fn output_name(&self) -> Option<&str> {
+        match self {
+            TemplateEntry::File(_) => None,
+            TemplateEntry::Table(template) => template.output_name.as_deref(),
+        }
+    }
+
+    pub fn entry(&self) -> Option<&str> {
+        match self {
+            TemplateEntry::File(_) => None,
+            TemplateEntry::Table(template) => template.entry.as_deref(),
+        }
+    }
+
+    /// the built-in template name for a `builtin:<name>` file
+    pub fn builtin(&self) -> Option<&str> {
+        self.file().strip_prefix(BUILTIN_TEMPLATE_PREFIX)
+    }
+
+    /// the Typst package for a `@namespace/name:version` file
+    pub fn package(&self) -> Option<anyhow::Result<PackageSpec>> {
+        PackageSpec::parse(self.file())
+    }
+
+    /// the name of the template file or directory once copied to `typst/templates/`
+    pub fn target_file_name(&self) -> anyhow::Result<String> {
+        if let Some(name) = self.builtin() {
+            return Ok(BuiltinTemplate::find(name)?.file_name());
+        }
+        Path::new(self.file())
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow::anyhow!("invalid template file {}", self.file()))
+    }
+}
+
+/// Where the book level typst file imports a template from
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedTemplate {
+    /// the `#import` path, relative to the typst folder, or the package spec
+    pub import: String,
+
+    /// the file defining the entry function
+    pub source: PathBuf,
+}
+
+/// The signature of a function defined in a template, read from the template source
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TemplateSignature {
+    /// the function name
+    pub function: String,
+
+    /// positional parameters, the last one receives the document body
+    pub positional: Vec<String>,
+
+    /// named parameters, they all have a default value
+    pub named: Vec<String>,
+
+    /// whether the function takes any other named argument with `..args`
+    pub accepts_any: bool,
+}
+
+impl TemplateSignature {
+    /// Parse the template source and find the entry function.
+    ///
+    /// Without an explicit `entry`, `best_practice_template` is used when it exists,
+    /// otherwise the only top level function taking a positional parameter for the body.
+    pub fn parse(source: &str, entry: Option<&str>) -> anyhow::Result<TemplateSignature> {
+        let functions = Self::parse_functions(source)?;
+        let find = |name: &str| functions.iter().rev().find(|function| function.function == name).cloned();
+        if let Some(entry) = entry {
+            return find(entry).ok_or_else(|| anyhow::anyhow!("the template doesn't define the entry function {}", entry));
+        }
+        if let Some(function) = find(BEST_PRACTICE_TEMPLATE) {
+            return Ok(function);
+        }
+        let candidates: Vec<&TemplateSignature> = functions.iter().filter(|function| !function.positional.is_empty()).collect();
+        match candidates.as_slice() {
+            [function] => Ok((*function).clone()),
+            [] => Err(anyhow::anyhow!("the template doesn't define a function taking the document body")),
+            _ => {
+                let names: Vec<&str> = candidates.iter().map(|function| function.function.as_str()).collect();
+                Err(anyhow::anyhow!("the template defines several functions ({}), set `entry` to pick one", names.join(", ")))
+            }
+        }
+    }
+
+    /// The top level functions of a template source
+    pub fn parse_functions(source: &str) -> anyhow::Result<Vec<TemplateSignature>> {
+        let root = typst_syntax::parse(source);
+        if root.erroneous() {
+            let errors: Vec<String> = root.errors().into_iter().map(|error| error.message.to_string()).collect();
+            return Err(anyhow::anyhow!("failed to parse the template: {}", errors.join("; ")));
+        }
+        let markup = root.cast::<ast::Markup>().unwrap_or_default();
+        Ok(markup
+            .exprs()
+            .filter_map(|expr| match expr {
+                ast::Expr::Let(binding) => match binding.init() {
+                    Some(ast::Expr::Closure(closure)) => closure.name().map(|name| Self::from_closure(name.as_str(), closure)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub fn from_file(path: &Path, entry: Option<&str>) -> anyhow::Result<TemplateSignature> {
+        let source = std::fs::read_to_string(path)?;
+        Self::parse(&source, entry).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    }
+
+    fn from_closure(name: &str, closure: ast::Closure) -> TemplateSignature {
+        let mut signature = TemplateSignature { function: name.to_string(), ..Default::default() };
+        for param in closure.params().children() {
+            match param {
+                // a destructuring pattern or `_` is kept as written, it can't be passed by name
+                ast::Param::Pos(ast::Pattern::Normal(ast::Expr::Ident(ident))) => signature.positional.push(ident.as_str().to_string()),
+                ast::Param::Pos(pattern) => signature.positional.push(pattern.to_untyped().clone().into_text().to_string()),
+                ast::Param::Named(named) => signature.named.push(named.name().as_str().to_string()),
+                ast::Param::Spread(_) => signature.accepts_any = true,
+            }
+        }
+        signature
+    }
+
+    /// positional parameters a value has to be given for, all but the document body
+    pub fn required(&self) -> &[String] {
+        match self.positional.split_last() {
+            Some((_body, required)) => required,
+            None => &[],
+        }
+    }
+}
+
+impl Config {
+    /// Locate a prepared template: a package in the package path or cache,
+    /// a directory in `typst/templates/` with the entry point from its `typst.toml`, or a single file.
+    pub fn resolve_template(&self, template: &TemplateEntry, ctx: &mdbook::renderer::RenderContext) -> anyhow::Result<ResolvedTemplate> {
+        if let Some(spec) = template.package() {
+            let spec = spec?;
+            let dir = self.get_package_dir(&spec, ctx)?;
+            let entrypoint = read_entrypoint(&dir)?;
+            return Ok(ResolvedTemplate { import: spec.to_string(), source: dir.join(entrypoint) });
+        }
+        let file_name = template.target_file_name()?;
+        let target = self.get_typst_templates_dir(ctx).join(&file_name);
+        if target.is_dir() {
+            let entrypoint = read_entrypoint(&target)?;
+            return Ok(ResolvedTemplate {
+                import: format!("{}/{}/{}", TARGET_TEMPLATE_DIR, file_name, entrypoint),
+                source: target.join(entrypoint),
+            });
+        }
+        // if the dst file doesn't exist or is not a file, error out
+        if !target.is_file() {
+            return Err(anyhow::anyhow!("template file {} not found", target.display()));
+        }
+        Ok(ResolvedTemplate { import: format!("{}/{}", TARGET_TEMPLATE_DIR, file_name), source: target })
+    }
+
+    /// The effective configuration for one template, with the template table values applied over the global ones.
+    ///
+    /// When the template changes how chapters are converted (`chapter_imports`, `element_functions`, `max_width`, `max_height`),
+    /// its chapters are written to their own `chapters-<template>` folder.
+    pub fn for_template(&self, name: &str) -> Config {
+        let mut config = self.clone();
+        if let Some(TemplateEntry::Table(template)) = self.templates.get(name) {
+            config.template_parameters.extend(template.parameters.clone());
+            if template.chapter_imports.is_some() {
+                config.chapter_imports = template.chapter_imports.clone();
+            }
+            config.element_functions.extend(template.element_functions.clone());
+            config.max_width = template.max_width.or(self.max_width);
+            config.max_height = template.max_height.or(self.max_height);
+            config.chapter_filter = template.chapter_filter().or(&self.chapter_filter);
+
+            if config.chapter_imports != self.chapter_imports
+                || config.element_functions != self.element_functions
+                || config.max_width != self.max_width
+                || config.max_height != self.max_height
+            {
+                config.chapter_variant = Some(name.to_string());
+            }
+        }
+        config
+    }
+
+    /// The distinct configurations chapters have to be converted with, one per chapters folder
+    pub fn get_chapter_variants(&self) -> Vec<Config> {
+        if self.templates.is_empty() {
+            return vec![self.clone()];
+        }
+        let mut variants: Vec<Config> = Vec::new();
+        for name in self.templates.keys() {
+            let config = self.for_template(name);
+            if !variants.iter().any(|variant| variant.chapter_variant == config.chapter_variant) {
+                variants.push(config);
+            }
+        }
+        variants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_entry() {
+        let config: Config = toml::from_str(
+            r#"
+            max_width = 0.9
+            [templates]
+            screen = "screen.typ"
+            [templates.print]
+            file = "print.typ"
+            max_width = 0.5
+            exclude = ["internal/**"]
+            [templates.print.parameters]
+            doc_version = "print"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.templates["screen"], TemplateEntry::File("screen.typ".to_string()));
+        assert_eq!(config.templates["print"].file(), "print.typ");
+
+        let screen = config.for_template("screen");
+        assert_eq!(screen.chapter_variant, None);
+        assert_eq!(screen.max_width, Some(0.9));
+
+        let print = config.for_template("print");
+        assert_eq!(print.chapter_variant.as_deref(), Some("print"));
+        assert_eq!(print.max_width, Some(0.5));
+        assert_eq!(print.template_parameters["doc_version"].as_str(), Some("print"));
+        assert_eq!(print.chapter_filter.exclude, vec!["internal/**"]);
+        assert_eq!(config.get_chapter_variants().len(), 2);
+
+        // a typo or a missing file is reported with the key
+        let error = toml::from_str::<Config>("[templates.print]\nfile = \"print.typ\"\nparamters = {}").unwrap_err();
+        assert!(error.to_string().contains("unknown field `paramters`"), "{}", error);
+        let error = toml::from_str::<Config>("[templates.print]\noutput_name = \"print\"").unwrap_err();
+        assert!(error.to_string().contains("missing field `file`"), "{}", error);
+    }
+
+    #[test]
+    fn test_template_signature() {
+        let source = r#"
+#let helper(name: "x") = name
+#let best_practice_template(
+  doc_title: "Document (draft), v1",
+  // the authors
+  book_authors: ("Author Name",),
+  margins: (top: 1cm, bottom: 2cm),
+  body
+) = {
+  body
+}"#;
+        let functions = TemplateSignature::parse_functions(source).unwrap();
+        assert_eq!(functions.len(), 2);
+        assert_eq!(functions[0].named, vec!["name"]);
+        assert_eq!(functions[1].function, "best_practice_template");
+        assert_eq!(functions[1].named, vec!["doc_title", "book_authors", "margins"]);
+        assert_eq!(functions[1].positional, vec!["body"]);
+        assert!(!functions[1].accepts_any);
+        assert!(TemplateSignature::parse_functions("#let broken(").is_err());
+
+        let source = r#"
+#let helper(name: "x") = name
+#let report(
+  doc_title: "Document (draft), v1",
+  customer,
+  body
+) = body"#;
+        let signature = TemplateSignature::parse(source, None).unwrap();
+        assert_eq!(signature.function, "report");
+        assert_eq!(signature.named, vec!["doc_title"]);
+        assert_eq!(signature.required(), &["customer".to_string()]);
+
+        assert_eq!(TemplateSignature::parse(source, Some("helper")).unwrap().named, vec!["name"]);
+        assert!(TemplateSignature::parse(source, Some("missing")).is_err());
+        assert!(TemplateSignature::parse("#let a(body) = body\n#let b(body) = body", None).is_err());
+
+        let signature = TemplateSignature::parse("#let book((title, subtitle), body) = body", None).unwrap();
+        assert_eq!(signature.required(), &["(title, subtitle)".to_string()]);
+    }
+}
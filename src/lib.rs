@@ -5,7 +5,19 @@ use anyhow::{anyhow, Context as _};
 
 use once_cell::sync::Lazy;
 mod config;
+mod preprocessor;
+mod scaffold;
+mod standalone;
+mod watch;
 pub use config::Config;
+pub use config::converter::{Asset, AssetSource, Conversion, Converter, ConverterBuilder, ConverterOptions, HeadingAttributes, LinkTarget, ResolvedImage};
+pub use config::elements::{CodeBlock, DefaultElements, Element, ElementHandler, ImageElement};
+pub use config::escape::{escape_markup, raw_block, raw_inline};
+pub use config::prelude::{prelude_functions, PRELUDE, PRELUDE_FILE};
+pub use preprocessor::FrontMatterPreprocessor;
+pub use scaffold::{init_template, InitTemplateOptions};
+pub use standalone::{build_book, convert_file, parse_override, BuildOptions, ConfigOverride, ConvertOptions};
+pub use watch::watch;
 
 
 #[derive(Default)]
@@ -18,7 +30,7 @@ impl Renderer {
         Self { logfile: None }
     }
 
-    const NAME: &'static str = "typstpdf";
+    pub(crate) const NAME: &'static str = "typstpdf";
     const CONFIG_KEY: &'static str = "output.typstpdf";
 }
 
@@ -1,12 +1,208 @@
 use std::{
     env,
     io::{self, Write},
+    path::PathBuf,
     process,
 };
 
 use anyhow::Context;
-use mdbook::Renderer;
+use clap::{Args, Parser, Subcommand};
+use mdbook::{preprocess::{CmdPreprocessor, Preprocessor}, Renderer};
+use mdbook_typstpdf::{parse_override, ConfigOverride};
 
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run as an mdBook preprocessor that strips chapter front matter for other renderers
+    FrontMatter {
+        #[command(subcommand)]
+        command: Option<PreprocessorCommand>,
+    },
+    /// Create a starter template directory and print the matching book.toml configuration
+    InitTemplate {
+        /// the template directory to create
+        #[arg(default_value = "typst-template")]
+        dir: PathBuf,
+        /// the template file name
+        #[arg(long, default_value = "template.typ")]
+        file: String,
+        /// overwrite existing files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Convert one markdown file to PDF, PNG, SVG or Typst, without a book
+    Convert {
+        /// the markdown file
+        input: PathBuf,
+        /// the output file, its extension picks the format (.pdf, .png, .svg or .typ).
+        /// defaults to the input file with a .pdf extension.
+        /// .typ output also writes mdbook-prelude.typ and an __images folder next to it,
+        /// an existing mdbook-prelude.typ with a different content is an error
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Build the PDF of a book folder, without running the other renderers
+    Build {
+        /// the book root folder, with book.toml
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// the output folder, replaces `build.build-dir` from book.toml
+        #[arg(short, long)]
+        dest_dir: Option<PathBuf>,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Build the PDF, then rebuild it whenever the book sources, book.toml or the templates change
+    Watch {
+        /// the book root folder, with book.toml
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+}
+
+/// `[output.typstpdf]` options, they override book.toml
+#[derive(Args, Clone, Debug, Default)]
+struct ConfigArgs {
+    /// the folder with the Typst templates
+    #[arg(long)]
+    template_dir: Option<String>,
+    /// the built-in template used without `templates`, or `none`
+    #[arg(long)]
+    default_template: Option<String>,
+    /// an output format, can be repeated
+    #[arg(long = "format", value_parser = ["pdf", "png", "svg"])]
+    formats: Vec<String>,
+    /// the resolution of PNG pages
+    #[arg(long)]
+    ppi: Option<f64>,
+    /// the PDF standards to conform to, comma separated, like `a-2b`; the installed typst decides which are supported
+    #[arg(long)]
+    pdf_standard: Option<String>,
+    /// also compile every top level chapter into its own document
+    #[arg(long, value_parser = ["chapter"])]
+    split: Option<String>,
+    /// a folder with fonts, can be repeated
+    #[arg(long = "font-path")]
+    font_paths: Vec<String>,
+    /// only use the fonts from --font-path and the templates
+    #[arg(long)]
+    ignore_system_fonts: bool,
+    /// the local Typst package folder
+    #[arg(long)]
+    package_path: Option<String>,
+    /// the Typst package cache folder
+    #[arg(long)]
+    package_cache_path: Option<String>,
+    /// a template asset pattern, can be repeated
+    #[arg(long = "asset")]
+    assets: Vec<String>,
+    /// Typst code added at the beginning of each chapter
+    #[arg(long)]
+    chapter_imports: Option<String>,
+    /// the maximum image width, between 0.0 and 1.0
+    #[arg(long)]
+    max_width: Option<f64>,
+    /// the maximum image height, between 0.0 and 1.0
+    #[arg(long)]
+    max_height: Option<f64>,
+    /// a chapter pattern to include, can be repeated
+    #[arg(long)]
+    include: Vec<String>,
+    /// a chapter pattern to exclude, can be repeated
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// an audience whose chapters are included, can be repeated
+    #[arg(long = "audience")]
+    audiences: Vec<String>,
+    /// how many conversions and compilations run at the same time
+    #[arg(long)]
+    jobs: Option<usize>,
+    /// convert every chapter again
+    #[arg(long)]
+    no_cache: bool,
+    /// the chapter cache folder
+    #[arg(long)]
+    cache_dir: Option<String>,
+    /// keep the generated typst files
+    #[arg(long)]
+    keep_typst_files: bool,
+    /// a template parameter, `name=value`, can be repeated
+    #[arg(long = "param", value_parser = parse_override)]
+    params: Vec<ConfigOverride>,
+    /// any other option, `key=value` with a TOML value, like `templates.print='print.typ'`, can be repeated
+    #[arg(long = "set", value_parser = parse_override)]
+    set: Vec<ConfigOverride>,
+}
+
+impl ConfigArgs {
+    fn overrides(self) -> Vec<ConfigOverride> {
+        let mut overrides: Vec<ConfigOverride> = Vec::new();
+        let mut set = |key: &str, value: toml::Value| overrides.push((key.to_string(), value));
+        let strings = |values: Vec<String>| toml::Value::from(values);
+        for (key, value) in [
+            ("template_dir", self.template_dir),
+            ("default_template", self.default_template),
+            ("pdf_standard", self.pdf_standard),
+            ("split", self.split),
+            ("package_path", self.package_path),
+            ("package_cache_path", self.package_cache_path),
+            ("chapter_imports", self.chapter_imports),
+            ("cache_dir", self.cache_dir),
+        ] {
+            if let Some(value) = value {
+                set(key, toml::Value::from(value));
+            }
+        }
+        for (key, value) in [("ppi", self.ppi), ("max_width", self.max_width), ("max_height", self.max_height)] {
+            if let Some(value) = value {
+                set(key, toml::Value::from(value));
+            }
+        }
+        for (key, values) in [
+            ("formats", self.formats),
+            ("font_paths", self.font_paths),
+            ("assets", self.assets),
+            ("include", self.include),
+            ("exclude", self.exclude),
+            ("audiences", self.audiences),
+        ] {
+            if !values.is_empty() {
+                set(key, strings(values));
+            }
+        }
+        if let Some(jobs) = self.jobs {
+            set("jobs", toml::Value::Integer(jobs as i64));
+        }
+        if self.ignore_system_fonts {
+            set("ignore_system_fonts", toml::Value::Boolean(true));
+        }
+        if self.no_cache {
+            set("cache", toml::Value::Boolean(false));
+        }
+        if self.keep_typst_files {
+            set("keep_typst_files", toml::Value::Boolean(true));
+        }
+        for (name, value) in self.params {
+            set(&format!("template_parameters.{}", name), value);
+        }
+        overrides.extend(self.set);
+        overrides
+    }
+}
+
+#[derive(Subcommand)]
+enum PreprocessorCommand {
+    /// Check whether a renderer is supported by this preprocessor
+    Supports { renderer: String },
+}
 
 fn main() {
     if let Err(err) = try_main() {
@@ -18,9 +214,46 @@ fn main() {
 fn try_main() -> anyhow::Result<()> {
     init_logger();
 
-    let ctx = mdbook::renderer::RenderContext::from_json(io::stdin().lock())
-        .context("unable to parse mdBook context")?;
-    mdbook_typstpdf::Renderer::new().render(&ctx)
+    let cli = Cli::parse();
+    match cli.command {
+        // without a subcommand, we're invoked by mdBook as a renderer
+        None => {
+            let ctx = mdbook::renderer::RenderContext::from_json(io::stdin().lock())
+                .context("unable to parse mdBook context")?;
+            mdbook_typstpdf::Renderer::new().render(&ctx)
+        }
+        Some(Command::FrontMatter { command }) => run_front_matter_preprocessor(command),
+        Some(Command::InitTemplate { dir, file, force }) => {
+            let snippet = mdbook_typstpdf::init_template(&mdbook_typstpdf::InitTemplateOptions { dir, file_name: file, force })?;
+            println!("Add this to your book.toml:\n\n{}", snippet);
+            Ok(())
+        }
+        Some(Command::Convert { input, output, config }) => {
+            let options = mdbook_typstpdf::ConvertOptions { input, output, overrides: config.overrides() };
+            for file in mdbook_typstpdf::convert_file(&options)? {
+                println!("{}", file.display());
+            }
+            Ok(())
+        }
+        Some(Command::Build { dir, dest_dir, config }) => {
+            mdbook_typstpdf::build_book(&mdbook_typstpdf::BuildOptions { dir, dest_dir, overrides: config.overrides() })
+        }
+        Some(Command::Watch { dir }) => mdbook_typstpdf::watch(&dir),
+    }
+}
+
+fn run_front_matter_preprocessor(command: Option<PreprocessorCommand>) -> anyhow::Result<()> {
+    let preprocessor = mdbook_typstpdf::FrontMatterPreprocessor::new();
+    if let Some(PreprocessorCommand::Supports { renderer }) = command {
+        // mdBook checks the exit code to decide whether to run the preprocessor for this renderer
+        process::exit(if preprocessor.supports_renderer(&renderer) { 0 } else { 1 });
+    }
+
+    let (ctx, book) = CmdPreprocessor::parse_input(io::stdin().lock())
+        .context("unable to parse mdBook preprocessor input")?;
+    let book = preprocessor.run(&ctx, book)?;
+    serde_json::to_writer(io::stdout().lock(), &book).context("unable to write the processed book")?;
+    Ok(())
 }
 
 // Adapted from mdbook's main.rs for consistency in log format
@@ -0,0 +1,46 @@
+use mdbook::book::{Book, BookItem};
+use mdbook::preprocess::{Preprocessor, PreprocessorContext};
+
+use crate::config::front_matter::FrontMatter;
+
+/// A companion preprocessor that strips chapter front matter for every renderer
+/// except this one, so the YAML/TOML block doesn't show up in the HTML output.
+///
+/// ```toml
+/// [preprocessor.typstpdf-front-matter]
+/// command = "mdbook-typstpdf front-matter"
+/// ```
+#[derive(Default)]
+pub struct FrontMatterPreprocessor;
+
+impl FrontMatterPreprocessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    const NAME: &'static str = "typstpdf-front-matter";
+}
+
+impl Preprocessor for FrontMatterPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, _ctx: &PreprocessorContext, mut book: Book) -> anyhow::Result<Book> {
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(chapter) = item {
+                let content = FrontMatter::strip(&chapter.content);
+                if content.len() != chapter.content.len() {
+                    log::debug!("Stripped front matter from chapter {}", chapter.name);
+                    chapter.content = content.to_string();
+                }
+            }
+        });
+        Ok(book)
+    }
+
+    fn supports_renderer(&self, renderer: &str) -> bool {
+        // the PDF renderer reads the front matter itself
+        renderer != crate::Renderer::NAME
+    }
+}
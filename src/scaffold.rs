@@ -0,0 +1,89 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::config::template::TemplateSignature;
+
+const STARTER_TEMPLATE: &str = include_str!("templates/starter.typ");
+
+/// Options of `mdbook-typstpdf init-template`
+#[derive(Clone, Debug)]
+pub struct InitTemplateOptions {
+    /// the template directory to create, it becomes `template_dir` in book.toml
+    pub dir: PathBuf,
+
+    /// the template file name inside `dir`
+    pub file_name: String,
+
+    /// overwrite existing files
+    pub force: bool,
+}
+
+/// Write a starter template directory: the template file with its entry function,
+/// an `images/` folder and `book.toml.snippet` with the matching `[output.typstpdf]` configuration.
+///
+/// Returns the book.toml snippet.
+pub fn init_template(options: &InitTemplateOptions) -> anyhow::Result<String> {
+    let template_file = options.dir.join(&options.file_name);
+    let snippet_file = options.dir.join("book.toml.snippet");
+    for file in [&template_file, &snippet_file] {
+        if file.exists() && !options.force {
+            return Err(anyhow::anyhow!("{} already exists, use --force to overwrite it", file.display()));
+        }
+    }
+
+    fs::create_dir_all(options.dir.join("images"))?;
+    // keep the empty folder in version control
+    fs::write(options.dir.join("images").join(".gitkeep"), "")?;
+    fs::write(&template_file, STARTER_TEMPLATE)?;
+    log::info!("created template file {}", template_file.display());
+
+    let snippet = book_toml_snippet(&options.dir, &options.file_name)?;
+    fs::write(&snippet_file, &snippet)?;
+    log::info!("created {}", snippet_file.display());
+    Ok(snippet)
+}
+
+fn book_toml_snippet(dir: &Path, file_name: &str) -> anyhow::Result<String> {
+    let signature = TemplateSignature::parse(STARTER_TEMPLATE, None)?;
+    let template_name = Path::new(file_name).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let mut snippet = String::new();
+    snippet.push_str("[output.typstpdf]\n");
+    snippet.push_str(&format!("template_dir = {}\n", toml::Value::from(dir.to_string_lossy().replace('\\', "/"))));
+    snippet.push_str("keep_typst_files = false\n\n");
+    snippet.push_str("[output.typstpdf.templates]\n");
+    snippet.push_str(&format!("{} = {}\n\n", toml_key(&template_name), toml::Value::from(file_name)));
+    snippet.push_str("[output.typstpdf.template_parameters]\n");
+    snippet.push_str("# values set here override the built-in ones from [book], git and the build date\n");
+    snippet.push_str(&format!("# {} also declares: {}\n", signature.function, signature.named.join(", ")));
+    snippet.push_str("doc_version = \"1.0\"\n");
+    Ok(snippet)
+}
+
+// bare keys only allow ASCII letters, digits, `_` and `-`
+fn toml_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        toml::Value::from(key).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starter_template() {
+        let signature = TemplateSignature::parse(STARTER_TEMPLATE, None).unwrap();
+        assert_eq!(signature.function, crate::config::BEST_PRACTICE_TEMPLATE);
+        assert!(signature.required().is_empty());
+
+        let snippet = book_toml_snippet(Path::new("support/typst template"), "my template.typ").unwrap();
+        let config: toml::Table = toml::from_str(&snippet).unwrap();
+        let config: crate::Config = config["output"]["typstpdf"].clone().try_into().unwrap();
+        assert_eq!(config.template_dir, "support/typst template");
+        assert_eq!(config.templates["my template"].file(), "my template.typ");
+    }
+}
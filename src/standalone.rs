@@ -0,0 +1,219 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use mdbook::{
+    book::{Book, Chapter},
+    renderer::RenderContext,
+    MDBook,
+};
+
+use crate::{
+    config::{assets::copy_tree, prelude::{PRELUDE, PRELUDE_FILE}, IMAGE_DIR},
+    Config, Renderer,
+};
+
+/// An `[output.typstpdf]` option set from the command line: the key, relative to the table,
+/// like `max_width` or `template_parameters.doc_version`, and its value.
+pub type ConfigOverride = (String, toml::Value);
+
+/// Options of `mdbook-typstpdf convert`
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    /// the markdown file
+    pub input: PathBuf,
+
+    /// the file to write, its extension picks the output: `.pdf`, `.png`, `.svg` or `.typ`.
+    /// defaults to the input with a `.pdf` extension
+    pub output: Option<PathBuf>,
+
+    /// options applied over the defaults
+    pub overrides: Vec<ConfigOverride>,
+}
+
+/// Options of `mdbook-typstpdf build`
+#[derive(Clone, Debug)]
+pub struct BuildOptions {
+    /// the book root folder, with book.toml
+    pub dir: PathBuf,
+
+    /// replaces `build.build-dir` from book.toml
+    pub dest_dir: Option<PathBuf>,
+
+    /// options applied over `[output.typstpdf]` from book.toml
+    pub overrides: Vec<ConfigOverride>,
+}
+
+/// Parse `key=value`, the value as TOML when it is one (`0.8`, `true`, `["a", "b"]`), otherwise as a string
+pub fn parse_override(arg: &str) -> anyhow::Result<ConfigOverride> {
+    let (key, value) = arg.split_once('=').ok_or_else(|| anyhow::anyhow!("expected key=value, got {}", arg))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(anyhow::anyhow!("missing key in {}", arg));
+    }
+    let value = match toml::from_str::<toml::Table>(&format!("value = {}", value.trim())) {
+        Ok(mut table) => table.remove("value").unwrap_or_else(|| toml::Value::from(value)),
+        Err(_) => toml::Value::from(value),
+    };
+    Ok((key.to_string(), value))
+}
+
+fn apply_overrides(config: &mut mdbook::Config, overrides: &[ConfigOverride]) -> anyhow::Result<()> {
+    // the renderer needs the table even when book.toml doesn't have one
+    if config.get(Renderer::CONFIG_KEY).is_none() {
+        config.set(Renderer::CONFIG_KEY, toml::Table::new())?;
+    }
+    for (key, value) in overrides {
+        config.set(format!("{}.{}", Renderer::CONFIG_KEY, key), value)?;
+    }
+    Ok(())
+}
+
+/// Build a book folder with the typstpdf renderer only, like `mdbook build` would,
+/// with the book's preprocessors.
+pub fn build_book(options: &BuildOptions) -> anyhow::Result<()> {
+    // the documents are named after the book folder, `.` doesn't have a name
+    let dir = options.dir.canonicalize().with_context(|| format!("book folder {} not found", options.dir.display()))?;
+    let mut book = MDBook::load(&dir).with_context(|| format!("unable to load the book in {}", dir.display()))?;
+    apply_overrides(&mut book.config, &options.overrides)?;
+    if let Some(dest_dir) = &options.dest_dir {
+        book.config.build.build_dir = dest_dir.clone();
+    }
+    book.execute_build_process(&Renderer::new())
+}
+
+/// Convert one markdown file to PDF, page images or Typst, without a book.
+///
+/// The file is rendered as a book with a single chapter, relative image paths are resolved from its folder.
+/// For `.typ` output the converted chapter is written with the prelude and its `__images` folder,
+/// an existing prelude with a different content is an error rather than overwritten.
+/// Returns the files of the output.
+pub fn convert_file(options: &ConvertOptions) -> anyhow::Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(&options.input).with_context(|| format!("unable to read {}", options.input.display()))?;
+    let input = options.input.canonicalize()?;
+    let root = input.parent().map(Path::to_path_buf).unwrap_or_default();
+    let file_name = input.file_name().map(PathBuf::from).unwrap_or_default();
+    let stem = input.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let output = options.output.clone().unwrap_or_else(|| options.input.with_extension("pdf"));
+    let extension = output.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    let mut book_config = mdbook::Config::default();
+    book_config.book.src = PathBuf::from(".");
+    book_config.book.title = Some(document_title(&content).unwrap_or_else(|| stem.clone()));
+    // the build folder is temporary, a chapter cache there would never be used again
+    book_config.set(format!("{}.cache", Renderer::CONFIG_KEY), false)?;
+    if extension != "typ" {
+        book_config.set(format!("{}.formats", Renderer::CONFIG_KEY), vec![extension.clone()])?;
+    }
+    apply_overrides(&mut book_config, &options.overrides)?;
+
+    let mut book = Book::new();
+    book.push_item(Chapter::new(&stem, content, &file_name, Vec::new()));
+    let build_dir = tempfile::tempdir()?;
+    let ctx = RenderContext::new(&root, book, book_config, build_dir.path());
+    let config: Config = ctx
+        .config
+        .get_deserialized_opt(Renderer::CONFIG_KEY)
+        .with_context(|| format!("Unable to deserialize {}", Renderer::CONFIG_KEY))?
+        .unwrap_or_default();
+
+    let output_dir = output.parent().map(Path::to_path_buf).unwrap_or_default();
+    if !output_dir.as_os_str().is_empty() {
+        fs::create_dir_all(&output_dir)?;
+    }
+
+    if extension == "typ" {
+        // another version's prelude, or a file of the same name, isn't replaced
+        let prelude = output_dir.join(PRELUDE_FILE);
+        if fs::read(&prelude).is_ok_and(|existing| existing != PRELUDE.as_bytes()) {
+            return Err(anyhow::anyhow!(
+                "{} exists with a different content, remove it or write the output to another folder",
+                prelude.display()
+            ));
+        }
+        config.generate_typst(&ctx)?;
+        let chapters_dir = config.get_chapters_dir(&ctx);
+        let typst = fs::read_to_string(chapters_dir.join(&file_name).with_extension("typ"))?;
+        // image paths start from the typst folder, where the chapter is now the only file
+        let image_root = format!("\"/{}/{}/", config.get_chapters_dir_name(), IMAGE_DIR);
+        fs::write(&output, typst.replace(&image_root, &format!("\"/{}/", IMAGE_DIR)))?;
+        if !prelude.exists() {
+            fs::write(&prelude, PRELUDE)?;
+        }
+        let mut written = vec![output.clone(), prelude];
+        let image_dir = chapters_dir.join(IMAGE_DIR);
+        // the folder is there even when the chapter has no images
+        if fs::read_dir(&image_dir).is_ok_and(|mut entries| entries.next().is_some()) {
+            copy_tree(&image_dir, &output_dir.join(IMAGE_DIR), None)?;
+            written.push(output_dir.join(IMAGE_DIR));
+        }
+        return Ok(written);
+    }
+
+    config.renderer(&ctx)?;
+    // the documents are named after the input folder, they're renamed after the output file
+    let book_name = config.get_book_name(None, &ctx);
+    let output_stem = output.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or(stem);
+    let mut written = Vec::new();
+    for format in &config.formats {
+        let format_dir = config.get_output_dir(&ctx).join(format.dir_name());
+        let Ok(entries) = fs::read_dir(&format_dir) else { continue };
+        let mut files: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        files.sort();
+        for file in files {
+            let name = file.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            let name = match name.strip_prefix(&book_name) {
+                Some(rest) => format!("{}{}", output_stem, rest),
+                None => name,
+            };
+            let target = output_dir.join(name);
+            fs::copy(&file, &target)?;
+            written.push(target);
+        }
+    }
+    Ok(written)
+}
+
+// the first level 1 heading, the document title
+fn document_title(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_to_typst() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("README.md");
+        fs::write(&input, "# Read Me\n\nSome *text*.\n").unwrap();
+        let options = ConvertOptions {
+            input,
+            output: Some(dir.path().join("out").join("readme.typ")),
+            overrides: vec![parse_override("chapter_imports=#import \"@preview/x:0.1.0\": *").unwrap()],
+        };
+        let written = convert_file(&options).unwrap();
+        assert_eq!(written, vec![dir.path().join("out").join("readme.typ"), dir.path().join("out").join(PRELUDE_FILE)]);
+        let typst = fs::read_to_string(&written[0]).unwrap();
+        assert!(typst.starts_with("#import \"/mdbook-prelude.typ\": *\n#import \"@preview/x:0.1.0\": *"));
+        assert!(typst.contains("_text_"));
+
+        // the same prelude again is fine, a different one is kept
+        assert!(convert_file(&options).is_ok());
+        fs::write(&written[1], "// edited").unwrap();
+        let error = convert_file(&options).unwrap_err().to_string();
+        assert!(error.contains("exists with a different content"), "{}", error);
+        assert_eq!(fs::read_to_string(&written[1]).unwrap(), "// edited");
+
+        assert_eq!(parse_override("max_width = 0.8").unwrap(), ("max_width".to_string(), toml::Value::Float(0.8)));
+        assert_eq!(parse_override("template_parameters.doc_version=1.0-rc").unwrap().1, toml::Value::from("1.0-rc"));
+        assert!(parse_override("=1").is_err());
+    }
+}
@@ -0,0 +1,55 @@
+// Built-in "article" template of mdbook-typstpdf:
+// compact title block, no table of contents, numbered sections and page numbers.
+
+#let mdbook-article(
+  book_title: none,
+  book_authors: (),
+  book_description: none,
+  book_language: "en",
+  build_date: none,
+  paper: "a4",
+  font_size: 10.5pt,
+  body,
+) = {
+  set document(title: book_title, author: book_authors)
+  set text(lang: book_language, size: font_size)
+  set par(justify: true)
+  set page(paper: paper, margin: (x: 2cm, y: 2.2cm), numbering: "1")
+  set heading(numbering: "1.1")
+
+  show raw.where(block: true): it => block(
+    width: 100%,
+    fill: luma(246),
+    inset: 8pt,
+    radius: 3pt,
+    it,
+  )
+  show raw.where(block: false): it => box(
+    fill: luma(240),
+    inset: (x: 3pt),
+    outset: (y: 3pt),
+    radius: 2pt,
+    it,
+  )
+  show quote.where(block: true): it => pad(left: 1.5em, emph(it.body))
+  set table(stroke: 0.5pt + luma(180), inset: 5pt)
+  show table.cell.where(y: 0): strong
+  show table: it => align(center, it)
+  show image: it => align(center, it)
+
+  align(center)[
+    #if book_title != none {
+      text(size: 20pt, weight: "bold", book_title)
+    }
+    #v(4pt)
+    #book_authors.join(", ")
+    #if build_date != none [ \ #build_date.display("[month repr:long] [day padding:none], [year]")]
+    #if book_description != none {
+      v(8pt)
+      block(width: 85%, text(size: 9.5pt, emph(book_description)))
+    }
+  ]
+  v(1em)
+
+  body
+}
@@ -0,0 +1,114 @@
+// Built-in "book" template of mdbook-typstpdf:
+// title page, table of contents, chapters starting on a new page,
+// running headers with the chapter name and page numbers.
+// Parameters without a value in book.toml keep the defaults below.
+
+#let mdbook-book(
+  book_title: none,
+  book_authors: (),
+  book_description: none,
+  book_language: "en",
+  build_date: none,
+  doc_version: none,
+  paper: "a4",
+  font_size: 11pt,
+  accent: rgb("#1f4e79"),
+  outline_depth: 3,
+  body,
+) = {
+  set document(title: book_title, author: book_authors)
+  set page(paper: paper, margin: (x: 2.5cm, y: 2.5cm))
+  set text(lang: book_language, size: font_size)
+  set par(justify: true)
+  set heading(numbering: "1.1")
+
+  // chapters always open a new page
+  show heading.where(level: 1): it => {
+    pagebreak(weak: true)
+    v(2cm)
+    set text(size: 22pt, fill: accent)
+    block(below: 1.2cm, it)
+  }
+  show heading: set text(fill: accent)
+
+  // code blocks and inline code
+  show raw.where(block: true): it => block(
+    width: 100%,
+    fill: luma(246),
+    stroke: (left: 2pt + luma(200)),
+    inset: (x: 10pt, y: 8pt),
+    radius: 3pt,
+    it,
+  )
+  show raw.where(block: false): it => box(
+    fill: luma(240),
+    inset: (x: 3pt),
+    outset: (y: 3pt),
+    radius: 2pt,
+    it,
+  )
+
+  // block quotes
+  show quote.where(block: true): it => block(
+    width: 100%,
+    fill: luma(250),
+    stroke: (left: 3pt + accent.lighten(50%)),
+    inset: (x: 12pt, y: 8pt),
+    it.body,
+  )
+
+  // tables
+  set table(stroke: 0.5pt + luma(180), inset: 6pt)
+  show table.cell.where(y: 0): strong
+  show table: it => align(center, it)
+  show image: it => align(center, it)
+
+  // title page
+  page(numbering: none, header: none)[
+    #v(30%)
+    #align(center)[
+      #if book_title != none {
+        text(size: 28pt, weight: "bold", fill: accent, book_title)
+      }
+      #if book_description != none {
+        v(0.6cm)
+        text(size: 14pt, book_description)
+      }
+      #v(2cm)
+      #if book_authors.len() > 0 {
+        text(size: 13pt, book_authors.join(", "))
+      }
+      #v(0.6cm)
+      #if doc_version != none [Version #doc_version \ ]
+      #if build_date != none {
+        build_date.display("[month repr:long] [day padding:none], [year]")
+      }
+    ]
+  ]
+
+  // table of contents
+  page(numbering: "i", header: none)[
+    #outline(depth: outline_depth, indent: auto)
+  ]
+
+  // running header with the current chapter, except on the pages a chapter starts
+  set page(
+    numbering: "1",
+    header: context {
+      let page = here().page()
+      if query(heading.where(level: 1)).any(it => it.location().page() == page) {
+        return
+      }
+      let previous = query(heading.where(level: 1).before(here()))
+      if previous.len() > 0 {
+        set text(size: 9pt, fill: luma(100))
+        align(right, emph(previous.last().body))
+        v(-6pt)
+        line(length: 100%, stroke: 0.5pt + luma(180))
+      }
+    },
+  )
+  counter(page).update(1)
+
+  body
+}
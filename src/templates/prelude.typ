@@ -0,0 +1,27 @@
+// Prelude of mdbook-typstpdf, generated with the book:
+// the functions the chapters call for horizontal rules, images and task list markers.
+// A template shadows one by defining a function of the same name, e.g.
+// `#let mdbook-rule() = align(center, sym.ast.op)`, the book document hands it over with `mdbook-shadow`.
+// Image paths start with `/`, they're relative to the typst folder.
+
+#let mdbook-functions = state("mdbook-functions", (:))
+
+// the template's functions used instead of the defaults below, by name
+#let mdbook-shadow(..functions) = mdbook-functions.update(current => current + functions.named())
+
+#let mdbook-call(name, default, ..args) = context {
+  let function = mdbook-functions.get().at(name, default: default)
+  function(..args)
+}
+
+#let mdbook-rule() = mdbook-call("mdbook-rule", () => line(length: 100%))
+
+#let mdbook-image(path, alt: none, width: auto) = mdbook-call(
+  "mdbook-image",
+  (path, alt: none, width: auto) => figure(image(path, alt: alt, width: width), caption: none),
+  path,
+  alt: alt,
+  width: width,
+)
+
+#let mdbook-task(checked) = mdbook-call("mdbook-task", checked => if checked [\[x\]] else [\[ \]], checked)
@@ -0,0 +1,86 @@
+// Built-in "report" template of mdbook-typstpdf:
+// title block on the first page followed by the table of contents,
+// numbered sections without forced page breaks, page numbers in the footer.
+
+#let mdbook-report(
+  book_title: none,
+  book_authors: (),
+  book_description: none,
+  book_language: "en",
+  build_date: none,
+  doc_version: none,
+  paper: "a4",
+  font_size: 11pt,
+  accent: rgb("#2e5e4e"),
+  outline_depth: 2,
+  body,
+) = {
+  set document(title: book_title, author: book_authors)
+  set text(lang: book_language, size: font_size)
+  set par(justify: true)
+  set heading(numbering: "1.1")
+  show heading: set text(fill: accent)
+  show heading.where(level: 1): set block(above: 1.6em, below: 1em)
+
+  show raw.where(block: true): it => block(
+    width: 100%,
+    fill: luma(246),
+    inset: 8pt,
+    radius: 3pt,
+    it,
+  )
+  show raw.where(block: false): it => box(
+    fill: luma(240),
+    inset: (x: 3pt),
+    outset: (y: 3pt),
+    radius: 2pt,
+    it,
+  )
+  show quote.where(block: true): it => block(
+    stroke: (left: 2pt + accent),
+    inset: (left: 10pt, y: 4pt),
+    it.body,
+  )
+  set table(stroke: 0.5pt + luma(180), inset: 6pt)
+  show table.cell.where(y: 0): strong
+  show table: it => align(center, it)
+  show image: it => align(center, it)
+
+  set page(
+    paper: paper,
+    numbering: "1 / 1",
+    header: context {
+      if here().page() > 1 and book_title != none {
+        set text(size: 9pt, fill: luma(100))
+        book_title
+        h(1fr)
+        if doc_version != none [v#doc_version]
+        v(-6pt)
+        line(length: 100%, stroke: 0.5pt + luma(180))
+      }
+    },
+  )
+
+  // title block
+  block(width: 100%, inset: (bottom: 12pt), stroke: (bottom: 1pt + accent))[
+    #if book_title != none {
+      text(size: 24pt, weight: "bold", fill: accent, book_title)
+    }
+    #if book_description != none {
+      v(4pt)
+      text(size: 13pt, book_description)
+    }
+    #v(8pt)
+    #text(size: 10pt)[
+      #book_authors.join(", ")
+      #h(1fr)
+      #if doc_version != none [Version #doc_version]
+      #if build_date != none [ · #build_date.display("[year]-[month]-[day]")]
+    ]
+  ]
+
+  outline(depth: outline_depth, indent: auto)
+  pagebreak(weak: true)
+
+  body
+}
@@ -0,0 +1,105 @@
+// Starter template generated by `mdbook-typstpdf init-template`.
+//
+// mdbook-typstpdf imports `best_practice_template` from this file and applies it to the whole book:
+//
+//   #show: doc => best_practice_template(doc_title: ..., doc_version: ..., doc)
+//
+// Only the parameters declared below are passed, each one keeps its default
+// when no value is available. Values come from, in increasing priority:
+//   1. book.toml `[book]`, git and the build date (the built-in parameters below)
+//   2. `MDBOOK_TYPSTPDF_PARAM_<NAME>` environment variables and `parameter_env`
+//   3. `[output.typstpdf.template_parameters]` and the template's own `parameters` table
+// TOML values keep their type: strings, numbers, booleans, arrays, tables (dictionaries)
+// and dates (`datetime`). `{ content = "*Markdown*" }` is passed as content.
+//
+// Add, rename or remove parameters freely: any other name declared here can be set
+// in `template_parameters`. A positional parameter before `body` is required.
+
+#let best_practice_template(
+  // [book] title, also available as `book_title`
+  doc_title: "Document Title",
+  // [book] authors joined with ", ", or `book_authors` for the array
+  doc_author: "Author Name",
+  // [book] description, also available as `book_description`
+  abstract: none,
+  // [book] language
+  book_language: "en",
+  // the build date as "YYYY-MM-DD", or `build_date` for a Typst datetime
+  doc_date: none,
+  // `git describe --tags --always --dirty` of the book repository,
+  // `git_commit` and `git_commit_short` hold the commit hash
+  git_describe: none,
+  // not built in, set it in template_parameters
+  doc_version: "1.0",
+  body,
+) = {
+  set document(title: doc_title, author: doc_author)
+  set text(lang: book_language, size: 11pt)
+  set par(justify: true)
+  set heading(numbering: "1.1")
+
+  // images in the `images` folder next to this file are copied along with the template
+  // and can be used as `image("images/logo.png")`
+  set page(
+    paper: "a4",
+    margin: (x: 2.5cm, y: 2.5cm),
+    header: context {
+      if here().page() > 1 {
+        set text(size: 9pt)
+        doc_title
+        h(1fr)
+        [v#doc_version]
+      }
+    },
+    footer: context {
+      set text(size: 9pt)
+      if git_describe != none { git_describe }
+      h(1fr)
+      counter(page).display("1 / 1", both: true)
+    },
+  )
+
+  // code blocks (triple backticks) and inline code (single backtick)
+  show raw.where(block: true): it => block(
+    width: 100%,
+    fill: rgb("#f6f8fa"),
+    stroke: (left: 2pt + rgb("#e1e4e8")),
+    inset: (x: 8pt, y: 6pt),
+    radius: 4pt,
+    it,
+  )
+  show raw.where(block: false): it => box(fill: rgb("#f5f5f5"), inset: (x: 3pt), outset: (y: 3pt), radius: 2pt, it)
+
+  // block quotes (`> ...`), tables and images
+  show quote.where(block: true): it => block(stroke: (left: 3pt + luma(200)), inset: (left: 10pt, y: 4pt), it.body)
+  set table(stroke: 0.5pt + luma(180), inset: 6pt)
+  show table: it => align(center, it)
+  show image: it => align(center, it)
+
+  // cover page
+  page(header: none, footer: none)[
+    #v(35%)
+    #text(size: 26pt, weight: "bold", doc_title)
+    #v(1em)
+    #text(size: 13pt)[#doc_author #h(1fr) Version #doc_version]
+    #if doc_date != none [ \ #doc_date]
+    #if abstract != none {
+      v(2em)
+      block(width: 80%, emph(abstract))
+    }
+  ]
+
+  outline(depth: 3, indent: auto)
+  pagebreak()
+  counter(page).update(1)
+
+  body
+}
+
+// A template section: chapters with `pdf.template_section: appendix` in their
+// front matter are wrapped as `#appendix[#include "..."]`.
+#let appendix(body) = {
+  set heading(numbering: "A.1")
+  counter(heading).update(0)
+  body
+}
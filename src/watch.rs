@@ -0,0 +1,222 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use mdbook::MDBook;
+use notify::{RecursiveMode, Watcher};
+
+use crate::{Config, Renderer};
+
+// changes arriving this close together are built once, editors often write a file in several steps
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The folders `mdbook-typstpdf watch` looks at, from the loaded book
+#[derive(Clone, Debug, PartialEq)]
+struct WatchDirs {
+    root: PathBuf,
+    src: Option<PathBuf>,
+    template_dir: Option<PathBuf>,
+    build_dir: Option<PathBuf>,
+}
+
+/// What a batch of file changes affects
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Changes {
+    /// book.toml changed
+    config: bool,
+    /// changed files in the book source folder
+    chapters: Vec<PathBuf>,
+    /// changed files in `template_dir`
+    templates: Vec<PathBuf>,
+}
+
+impl WatchDirs {
+    // only book.toml is watched until the book loads
+    fn root_only(root: &Path) -> WatchDirs {
+        WatchDirs { root: root.to_path_buf(), src: None, template_dir: None, build_dir: None }
+    }
+
+    fn watches(&self) -> Vec<(PathBuf, RecursiveMode)> {
+        let mut watches = vec![(self.root.clone(), RecursiveMode::NonRecursive)];
+        for dir in [&self.src, &self.template_dir].into_iter().flatten() {
+            if dir.is_dir() {
+                watches.push((dir.clone(), RecursiveMode::Recursive));
+            }
+        }
+        watches
+    }
+
+    fn classify(&self, paths: &[PathBuf]) -> Changes {
+        let mut changes = Changes::default();
+        for path in paths {
+            // our own output, when the build folder is inside a watched one
+            if self.build_dir.as_ref().is_some_and(|dir| path.starts_with(dir)) {
+                continue;
+            }
+            if path == &self.root.join("book.toml") {
+                changes.config = true;
+            } else if self.src.as_ref().is_some_and(|dir| path.starts_with(dir)) {
+                if !changes.chapters.contains(path) {
+                    changes.chapters.push(path.clone());
+                }
+            } else if self.template_dir.as_ref().is_some_and(|dir| path.starts_with(dir)) && !changes.templates.contains(path) {
+                changes.templates.push(path.clone());
+            }
+        }
+        changes
+    }
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        !self.config && self.chapters.is_empty() && self.templates.is_empty()
+    }
+}
+
+/// Build the book with the typstpdf renderer, then build it again whenever the book sources,
+/// book.toml or the files in `template_dir` change, until interrupted.
+///
+/// The typst files are kept between builds, so only the affected steps are redone:
+/// changed sources convert the chapters again (unchanged ones come from the chapter cache),
+/// changed template files are copied again, and the documents are always compiled.
+/// A changed book.toml, or a failed previous build, runs the full build.
+/// Typst diagnostics point back to the markdown and template files.
+pub fn watch(root: &Path) -> anyhow::Result<()> {
+    let root = root.canonicalize().with_context(|| format!("book folder {} not found", root.display()))?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let mut watched: Vec<(PathBuf, RecursiveMode)> = Vec::new();
+    // None runs the full build
+    let mut changes: Option<Changes> = None;
+
+    loop {
+        // the book is loaded again for every build, book.toml may have moved the watched folders
+        let (built, dirs) = match load_book(&root) {
+            Ok((book, config, dirs)) => {
+                let result = match &changes {
+                    Some(changes) if !changes.config => rebuild(&book, &config, changes),
+                    _ => book.execute_build_process(&Renderer::new()),
+                };
+                match &result {
+                    Ok(()) => log::info!("build finished"),
+                    Err(e) => log::error!("build failed: {:#}", e),
+                }
+                (result.is_ok(), dirs)
+            }
+            Err(e) => {
+                log::error!("unable to load the book: {:#}", e);
+                (false, WatchDirs::root_only(&root))
+            }
+        };
+
+        let watches = dirs.watches();
+        if watches != watched {
+            for (path, _) in &watched {
+                // the folder may be gone already
+                let _ = watcher.unwatch(path);
+            }
+            for (path, mode) in &watches {
+                watcher.watch(path, *mode).with_context(|| format!("unable to watch {}", path.display()))?;
+            }
+            watched = watches;
+        }
+        let names: Vec<String> = watched.iter().map(|(path, _)| path.display().to_string()).collect();
+        log::info!("watching {} for changes", names.join(", "));
+
+        let next = wait_for_changes(&receiver, &dirs)?;
+        if next.config {
+            log::info!("book.toml changed");
+        }
+        if !next.chapters.is_empty() {
+            log::info!("{} source files changed", next.chapters.len());
+        }
+        if !next.templates.is_empty() {
+            log::info!("{} template files changed", next.templates.len());
+        }
+        // the steps skipped by a rebuild need the typst files of a successful build
+        changes = built.then_some(next);
+    }
+}
+
+// redo the steps `changes` affects, on the book as the preprocessors leave it
+fn rebuild(book: &MDBook, config: &Config, changes: &Changes) -> anyhow::Result<()> {
+    let (preprocessed, _) = book.preprocess_book(&Renderer::new())?;
+    let ctx = mdbook::renderer::RenderContext::new(&book.root, preprocessed, book.config.clone(), book.build_dir_for(Renderer::NAME));
+    config.rebuild(&ctx, !changes.templates.is_empty(), !changes.chapters.is_empty())
+}
+
+// load the book with the typst files kept, and find the folders to watch
+fn load_book(root: &Path) -> anyhow::Result<(MDBook, Config, WatchDirs)> {
+    let mut book = MDBook::load(root)?;
+    book.config.set(format!("{}.keep_typst_files", Renderer::CONFIG_KEY), true)?;
+    let config: Config = book
+        .config
+        .get_deserialized_opt(Renderer::CONFIG_KEY)
+        .with_context(|| format!("Unable to deserialize {}", Renderer::CONFIG_KEY))?
+        .unwrap_or_default();
+    let build_dir = book.build_dir_for(Renderer::NAME);
+    let ctx = mdbook::renderer::RenderContext::new(root, book.book.clone(), book.config.clone(), &build_dir);
+    let dirs = WatchDirs {
+        root: root.to_path_buf(),
+        src: Some(book.source_dir()),
+        template_dir: Some(config.get_template_dir(&ctx)),
+        build_dir: Some(build_dir),
+    };
+    Ok((book, config, dirs))
+}
+
+fn wait_for_changes(receiver: &mpsc::Receiver<notify::Result<notify::Event>>, dirs: &WatchDirs) -> anyhow::Result<Changes> {
+    loop {
+        let mut paths = event_paths(receiver.recv().context("the file watcher stopped")?);
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            paths.extend(event_paths(event));
+        }
+        let changes = dirs.classify(&paths);
+        if !changes.is_empty() {
+            return Ok(changes);
+        }
+    }
+}
+
+fn event_paths(event: notify::Result<notify::Event>) -> Vec<PathBuf> {
+    match event {
+        Ok(event) if event.kind.is_access() => Vec::new(),
+        Ok(event) => event.paths,
+        Err(e) => {
+            log::warn!("file watcher error: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_changes() {
+        let root = PathBuf::from("/books/guide");
+        let dirs = WatchDirs {
+            root: root.clone(),
+            src: Some(root.join("src")),
+            template_dir: Some(root.join("typst-template")),
+            build_dir: Some(root.join("src").join("book")),
+        };
+        let paths = vec![
+            root.join("book.toml"),
+            root.join("src").join("intro.md"),
+            root.join("src").join("intro.md"),
+            root.join("src").join("book").join("typst").join("guide.typ"),
+            root.join("typst-template").join("template.typ"),
+            root.join(".book.toml.swp"),
+        ];
+        let changes = dirs.classify(&paths);
+        assert!(changes.config);
+        assert_eq!(changes.chapters, vec![root.join("src").join("intro.md")]);
+        assert_eq!(changes.templates, vec![root.join("typst-template").join("template.typ")]);
+        assert!(dirs.classify(&[root.join("README.md")]).is_empty());
+    }
+}